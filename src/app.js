@@ -58,11 +58,11 @@ function js_message_with_color(msg, show_error) {
     abm_logs.scrollTop = abm_logs.scrollHeight;
 }
 
-export function js_message(msg) {
+function js_message(msg) {
     js_message_with_color(msg, false);
 }
 
-export function js_error(msg) {
+function js_error(msg) {
     js_message_with_color(msg, true);
 }
 
@@ -243,7 +243,12 @@ class JsSliderValue {
     }
     set value(new_value) {
         this.numeric_value = new_value;
-        this.private_set_value(new_value)
+        this.text.value = new_value.toString();
+        if (this.logarithmic) {
+            this.private_set_slider_value(new_value > 0 ? this.private_calculate_slider_position(new_value) : this.slider_min);
+        } else {
+            this.private_set_slider_value(new_value);
+        }
     }
     get value() {
         return this.numeric_value;
@@ -351,6 +356,13 @@ class JsMinMax {
             }
         }
     }
+    set(min, max) {
+        this.current_min = min;
+        this.current_max = max;
+        this.mdc_min.value = min;
+        this.mdc_max.value = max;
+        this.valid = true;
+    }
     layout() {
         this.mdc_min.layout();
         this.mdc_max.layout();
@@ -360,28 +372,246 @@ class JsMinMax {
 function abm_layout() {
     window.abm.nAgents0.layout();
     window.abm.worldLength.layout();
+    window.abm.replicates.layout();
     window.abm.fps.layout();
-    // window.abm.example_range.layout();
+    window.abm.birth_rate.layout();
+    window.abm.immigration.layout();
+    window.abm.carrying_capacity.layout();
+    window.abm.awareness.layout();
+    window.abm.compliance.layout();
+    window.abm.detection_probability.layout();
+    window.abm.tracing_capacity.layout();
+    window.abm.quarantine_duration.layout();
+    window.abm.max_steps.layout();
+    window.abm.steps_per_frame.layout();
+    window.abm.prevalence_threshold.layout();
+    window.abm.graph_nodes.layout();
+    window.abm.net_metrics_interval.layout();
+    window.abm.record_from.layout();
+    window.abm.record_to.layout();
+    window.abm.sweep_x_range.layout();
+    window.abm.sweep_x_steps.layout();
+    window.abm.sweep_y_range.layout();
+    window.abm.sweep_y_steps.layout();
+    window.abm.sweep_runs.layout();
+    window.abm.sweep_time_steps.layout();
 }
 
-export function js_n0() {
-    return window.abm.nAgents0.value;
+// Parameters of the model and the figures, read from the controls whenever wasm asks for them.
+// Posting this object to the simulation worker copies the current values.
+function abm_params() {
+    return {
+        get n0() {
+            return window.abm.nAgents0.value;
+        },
+        get world_length() {
+            return window.abm.worldLength.value;
+        },
+        set world_length(value) {
+            window.abm.worldLength.value = value;
+        },
+        get replicates() {
+            return window.abm.replicates.value;
+        },
+        get infection_probability() {
+            return window.abm.infection_probability.value;
+        },
+        set infection_probability(value) {
+            window.abm.infection_probability.value = value;
+        },
+        get demography() {
+            return js_get_id("abm-demography").value;
+        },
+        set demography(value) {
+            js_get_id("abm-demography").value = value;
+        },
+        get birth_rate() {
+            return window.abm.birth_rate.value;
+        },
+        set birth_rate(value) {
+            window.abm.birth_rate.value = value;
+        },
+        get immigration() {
+            return window.abm.immigration.value;
+        },
+        set immigration(value) {
+            window.abm.immigration.value = value;
+        },
+        get carrying_capacity() {
+            return window.abm.carrying_capacity.value;
+        },
+        set carrying_capacity(value) {
+            window.abm.carrying_capacity.value = value;
+        },
+        get risk_perception() {
+            return js_get_id("abm-risk-perception").value;
+        },
+        set risk_perception(value) {
+            js_get_id("abm-risk-perception").value = value;
+        },
+        get awareness() {
+            return window.abm.awareness.value;
+        },
+        set awareness(value) {
+            window.abm.awareness.value = value;
+        },
+        get compliance() {
+            return window.abm.compliance.value;
+        },
+        set compliance(value) {
+            window.abm.compliance.value = value;
+        },
+        get detection_probability() {
+            return window.abm.detection_probability.value;
+        },
+        set detection_probability(value) {
+            window.abm.detection_probability.value = value;
+        },
+        get tracing_capacity() {
+            return window.abm.tracing_capacity.value;
+        },
+        set tracing_capacity(value) {
+            window.abm.tracing_capacity.value = value;
+        },
+        get quarantine_duration() {
+            return window.abm.quarantine_duration.value;
+        },
+        set quarantine_duration(value) {
+            window.abm.quarantine_duration.value = value;
+        },
+        get max_steps() {
+            return window.abm.max_steps.value;
+        },
+        set max_steps(value) {
+            window.abm.max_steps.value = value;
+        },
+        get stop_on_disease_extinction() {
+            return window.abm.stop_disease_extinction_switch.selected;
+        },
+        set stop_on_disease_extinction(value) {
+            window.abm.stop_disease_extinction_switch.selected = value;
+        },
+        get stop_on_population_extinction() {
+            return window.abm.stop_population_extinction_switch.selected;
+        },
+        set stop_on_population_extinction(value) {
+            window.abm.stop_population_extinction_switch.selected = value;
+        },
+        get prevalence_threshold() {
+            return window.abm.prevalence_threshold.value;
+        },
+        set prevalence_threshold(value) {
+            window.abm.prevalence_threshold.value = value;
+        },
+        get dashboard() {
+            return window.abm.dashboard.value;
+        },
+        get agent_series() {
+            return Array.from(js_get_id("abm-agent-series").selectedOptions).map(option => option.value).join(",");
+        },
+        get proportional() {
+            return window.abm.proportional_switch.selected;
+        },
+        get histogram_mode() {
+            return js_get_id("abm-histogram-mode").value;
+        },
+        get graph_nodes() {
+            return window.abm.graph_nodes.value;
+        },
+        get net_metrics_interval() {
+            return window.abm.net_metrics_interval.value;
+        },
+        get net_series() {
+            return Array.from(js_get_id("abm-net-series").selectedOptions).map(option => option.value).join(",");
+        },
+        get dark_figures() {
+            return window.abm.dark_figures_switch.selected;
+        },
+        get palette() {
+            return js_get_id("abm-palette").value;
+        },
+        get susceptible_color() {
+            return js_get_id("abm-susceptible-color").value;
+        },
+        get infected_color() {
+            return js_get_id("abm-infected-color").value;
+        },
+        get sweep_x() {
+            return js_get_id("abm-sweep-x").value;
+        },
+        get sweep_x_min() {
+            return window.abm.sweep_x_range.current_min;
+        },
+        get sweep_x_max() {
+            return window.abm.sweep_x_range.current_max;
+        },
+        get sweep_x_steps() {
+            return window.abm.sweep_x_steps.value;
+        },
+        get sweep_y() {
+            return js_get_id("abm-sweep-y").value;
+        },
+        get sweep_y_min() {
+            return window.abm.sweep_y_range.current_min;
+        },
+        get sweep_y_max() {
+            return window.abm.sweep_y_range.current_max;
+        },
+        get sweep_y_steps() {
+            return window.abm.sweep_y_steps.value;
+        },
+        get sweep_outcome() {
+            return js_get_id("abm-sweep-outcome").value;
+        },
+        get sweep_runs() {
+            return window.abm.sweep_runs.value;
+        },
+        get sweep_time_steps() {
+            return window.abm.sweep_time_steps.value;
+        }
+    };
 }
 
-export function js_world_length() {
-    return window.abm.worldLength.value;
-}
+// Default range of each parameter that a sweep can vary
+const abm_sweep_ranges = {
+    infection_probability: [0, 1],
+    birth_rate: [0, 0.1],
+    immigration: [0, 50],
+    carrying_capacity: [100, 5000],
+    awareness: [0, 20],
+    compliance: [0, 1],
+    detection_probability: [0, 1],
+    tracing_capacity: [0, 100],
+    quarantine_duration: [1, 28],
+};
 
-export function js_infection_probability() {
-    return window.abm.infection_probability.value;
+function rs_deploy_scenario() {
+    // Frames of the previous scenario that are still on their way are ignored
+    window.abm.scenario_id += 1;
+    window.abm.worker.postMessage({ type: "deploy", id: window.abm.scenario_id, params: window.abm.params, comparisons: window.abm.comparisons });
+    window.abm.rs_mod.rs_deploy_scenario();
+    abm_show_comparisons();
 }
 
-export function js_dark_figures() {
-    return window.abm.dark_figures_switch.checked;
-}
+// Same as MAX_COMPARISONS in src/lib.rs
+const abm_max_comparisons = 3;
 
-function rs_deploy_scenario() {
-    window.abm.rs_mod.rs_deploy_scenario();
+// Model parameters that comparison runs pin; the figure parameters are shared by all runs
+const abm_model_parameters = ["n0", "world_length", "infection_probability", "demography", "birth_rate", "immigration", "carrying_capacity", "risk_perception", "awareness", "compliance", "detection_probability", "tracing_capacity", "quarantine_duration", "max_steps", "stop_on_disease_extinction", "stop_on_population_extinction", "prevalence_threshold"];
+
+// Lists the parameters of each comparison run that differ from those on the page
+function abm_show_comparisons() {
+    let list = js_get_id("abm-comparisons");
+    list.replaceChildren();
+    window.abm.comparisons.forEach(function (params, idx) {
+        let differences = abm_model_parameters
+            .filter(name => params[name] !== window.abm.params[name])
+            .map(name => name.replaceAll("_", " ") + " " + params[name]);
+        let item = document.createElement("li");
+        item.innerText = "Run " + (idx + 2) + (differences.length === 0 ? ": same parameters as run 1" : ": " + differences.join(", "));
+        list.appendChild(item);
+    });
+    list.hidden = window.abm.comparisons.length === 0;
 }
 
 function frame_duration() {
@@ -390,10 +620,219 @@ function frame_duration() {
     return Math.trunc(1000.0 / window.abm.fps.value);
 }
 
-export function js_scenario(rs_step_closure) {
-    // let abm_body = js_get_id("abm-body");
+// Matches the canvases to the browser window, in device pixels so figures stay sharp
+function abm_resize_canvas() {
+    let ratio = window.devicePixelRatio || 1;
+    let width = window.innerWidth;
+    let height = window.innerHeight;
+    for (let id of ["abm-canvas", "abm-overlay"]) {
+        let abm_canvas = js_get_id(id);
+        abm_canvas.style.width = width + "px";
+        abm_canvas.style.height = height + "px";
+        abm_canvas.width = Math.round(width * ratio);
+        abm_canvas.height = Math.round(height * ratio);
+    }
+}
+
+// Position of a pointer event in canvas pixels
+function abm_canvas_point(event) {
+    let abm_canvas = event.target;
+    return [
+        Math.round(event.offsetX * abm_canvas.width / abm_canvas.clientWidth),
+        Math.round(event.offsetY * abm_canvas.height / abm_canvas.clientHeight),
+    ];
+}
+
+// Offers a file to the user as a download
+function abm_download(blob, filename) {
+    let url = URL.createObjectURL(blob);
+    let link = document.createElement("a");
+    link.href = url;
+    link.download = filename;
+    document.body.appendChild(link);
+    link.click();
+    link.remove();
+    setTimeout(() => URL.revokeObjectURL(url), 1000);
+}
+
+// Figures are exported with the size of the canvas on screen and a light theme
+function abm_export_svg() {
     let abm_canvas = js_get_id("abm-canvas");
-    // let abm_canvas_context = abm_canvas.getContext("2d");
+    return window.abm.rs_svg(abm_canvas.clientWidth, abm_canvas.clientHeight);
+}
+
+function abm_svg_button_handler(event) {
+    let svg = abm_export_svg();
+    if (svg.length > 0) {
+        abm_download(new Blob([svg], { type: "image/svg+xml" }), "abm-figures.svg");
+    }
+}
+
+function abm_events_button_handler(event) {
+    if (window.abm.rs_events !== undefined) {
+        let csv = window.abm.rs_events();
+        if (csv.length > 0) {
+            abm_download(new Blob([csv], { type: "text/csv" }), "abm-infections.csv");
+        }
+    }
+}
+
+function abm_png_button_handler(event) {
+    // The PNG holds the figures exactly as the canvas draws them
+    js_get_id("abm-canvas").toBlob(blob => abm_download(blob, "abm-figures.png"), "image/png");
+}
+
+function abm_stop_recording() {
+    if (window.abm.recording !== undefined) {
+        window.abm.recording.recorder.stop();
+        window.abm.recording = undefined;
+    }
+}
+
+// Captures each frame in the chosen range of time steps; the video plays at the pace of the simulation
+function js_frame(time_step) {
+    if (!window.abm.record_switch.selected || time_step > window.abm.record_to.value) {
+        abm_stop_recording();
+        return;
+    }
+    if (time_step < window.abm.record_from.value) {
+        return;
+    }
+    if (window.abm.recording !== undefined && window.abm.recording.time_step === time_step) {
+        // Redrawing the same time step, e.g. after a resize, adds no frames
+        return;
+    }
+    if (window.abm.recording === undefined) {
+        if (typeof MediaRecorder === "undefined") {
+            js_error("Error: This browser cannot record videos.");
+            window.abm.record_switch.selected = false;
+            return;
+        }
+        let stream = js_get_id("abm-canvas").captureStream(0);
+        let recorder = new MediaRecorder(stream, { mimeType: "video/webm" });
+        let chunks = [];
+        recorder.ondataavailable = function (event) {
+            if (event.data.size > 0) {
+                chunks.push(event.data);
+            }
+        };
+        recorder.onstop = function () {
+            abm_download(new Blob(chunks, { type: "video/webm" }), "abm-run.webm");
+            js_message("The video is ready.");
+        };
+        recorder.start();
+        window.abm.recording = { recorder: recorder, track: stream.getVideoTracks()[0] };
+        js_message("Recording from time step " + time_step + ".");
+    }
+    window.abm.recording.time_step = time_step;
+    window.abm.recording.track.requestFrame();
+    if (time_step >= window.abm.record_to.value) {
+        abm_stop_recording();
+    }
+}
+
+// Landscape cells show a tooltip; shift-click infects or disinfects them
+function abm_canvas_pointer(event, toggle) {
+    let tooltip = js_get_id("abm-tooltip");
+    let text = "";
+    if (window.abm.rs_cell !== undefined) {
+        let [x, y] = abm_canvas_point(event);
+        text = window.abm.rs_cell(x, y, toggle);
+    }
+    if (text.length > 0) {
+        tooltip.innerText = text;
+        tooltip.style.left = (event.clientX + 15) + "px";
+        tooltip.style.top = (event.clientY + 15) + "px";
+        tooltip.style.display = "block";
+    } else {
+        tooltip.style.display = "none";
+    }
+}
+
+// Time series charts show the values under the pointer on the overlay canvas.
+// Dragging zooms into the time axis, the wheel pans it, and a double click shows the whole run.
+function abm_clear_overlay() {
+    let overlay = js_get_id("abm-overlay");
+    overlay.getContext("2d").clearRect(0, 0, overlay.width, overlay.height);
+}
+
+function abm_chart_pointer_move(event) {
+    abm_clear_overlay();
+    if (window.abm.rs_hover !== undefined) {
+        let [x, y] = abm_canvas_point(event);
+        let drag = window.abm.chart_drag;
+        if (drag !== undefined) {
+            let overlay = js_get_id("abm-overlay");
+            let context = overlay.getContext("2d");
+            context.fillStyle = "rgba(128, 128, 128, 0.3)";
+            context.fillRect(Math.min(drag.x, x), 0, Math.abs(x - drag.x), overlay.height);
+        }
+        window.abm.rs_hover(x, y);
+    }
+}
+
+function abm_chart_pointer_down(event) {
+    if ((window.abm.rs_hover !== undefined) && (event.button == 0) && !event.shiftKey) {
+        let [x, y] = abm_canvas_point(event);
+        if (window.abm.rs_hover(x, y)) {
+            window.abm.chart_drag = { x: x, y: y };
+        }
+    }
+}
+
+function abm_chart_pointer_up(event) {
+    let drag = window.abm.chart_drag;
+    if (drag !== undefined) {
+        window.abm.chart_drag = undefined;
+        let [x, y] = abm_canvas_point(event);
+        if (Math.abs(x - drag.x) > 5) {
+            window.abm.rs_zoom(drag.x, x, drag.y, false);
+        }
+        abm_chart_pointer_move(event);
+    }
+}
+
+function abm_chart_wheel(event) {
+    if (window.abm.rs_zoom !== undefined) {
+        let [x, y] = abm_canvas_point(event);
+        if (window.abm.rs_zoom(x, x - Math.round(event.deltaY), y, true)) {
+            abm_chart_pointer_move(event);
+        }
+    }
+}
+
+function abm_chart_double_click(event) {
+    if (window.abm.rs_zoom !== undefined) {
+        let [x, y] = abm_canvas_point(event);
+        if (window.abm.rs_zoom(x, x, y, false)) {
+            abm_chart_pointer_move(event);
+        }
+    }
+}
+
+// Sends a landscape cell edit to the simulation worker
+function js_toggle_cell(idx) {
+    window.abm.worker.postMessage({ type: "toggle-cell", id: window.abm.scenario_id, idx: idx, params: window.abm.params });
+}
+
+// Follows the latest time step unless the user dragged the timeline back
+function js_timeline(latest_time_step) {
+    let timeline = js_get_id("abm-timeline");
+    let following = timeline.valueAsNumber >= timeline.max;
+    timeline.max = latest_time_step;
+    if (following) {
+        timeline.value = latest_time_step;
+    }
+    js_get_id("abm-timeline-label").innerText = timeline.value;
+}
+
+function js_scenario(rs_frame_closure, rs_render_closure, rs_svg_closure, rs_cell_closure, rs_hover_closure, rs_zoom_closure, rs_view_closure, rs_sweep_closure, rs_events_closure, rs_bands_closure) {
+    window.abm.rs_render = rs_render_closure;
+    window.abm.rs_svg = rs_svg_closure;
+    window.abm.rs_events = rs_events_closure;
+    window.abm.rs_cell = rs_cell_closure;
+    window.abm.rs_hover = rs_hover_closure;
+    window.abm.rs_zoom = rs_zoom_closure;
     let start_stop = js_get_id("start-stop");
     let start_stop_label = js_get_id("start-stop-label");
     let abm_running = false;
@@ -401,30 +840,132 @@ export function js_scenario(rs_step_closure) {
     let abm_frame_duration = frame_duration();
     let abm_allow_buttons = true; // Prevents spurious/anxious clicks
     let abm_allow_step = true; // Prevents simulation from going too fast
+    let scenario_id = window.abm.scenario_id;
+    // The sweep results outlive the run that drew them
+    if (window.abm.sweep !== undefined) {
+        rs_sweep_closure(window.abm.sweep);
+    }
+    function request_step(steps) {
+        abm_allow_step = false;
+        // The simulation worker runs the time steps and posts a frame back
+        window.abm.worker.postMessage({ type: "step", id: scenario_id, steps: steps, params: window.abm.params });
+    }
+    // In the fast mode each frame requests the next time steps, so the timer stops
+    function start_timer() {
+        clearInterval(interval_id);
+        interval_id = window.abm.fast_switch.selected ? undefined : setInterval(step_handler, abm_frame_duration);
+    }
     function step_handler() {
         if (abm_allow_step) {
-            abm_allow_step = false;
-            // The following code was supposed to scale the canvas to fit into the browser window. Unfortunately, Chrome and plotters distort the output plots.
-            // let width = abm_body.offsetWidth;
-            // let height = abm_body.offsetHeight;
-            // abm_canvas.width = width;
-            // abm_canvas.height = height;
-            // let scaleX = width / 1280;
-            // let scaleY = height / 720;
-            // let abm_scale = Math.min(scaleX, scaleY);
-            // js_message("Width " + width + " Height " + height + " scaleX " + scaleX + " scaleY " + scaleY + " abm_scale " + abm_scale + ".");
-            // abm_canvas_context.scale(scaleX, scaleY);
             let new_frame_duration = frame_duration();
             if (window.abm.fps.valid && (abm_frame_duration != new_frame_duration)) {
                 abm_frame_duration = new_frame_duration;
-                clearInterval(interval_id);
-                interval_id = setInterval(step_handler, abm_frame_duration);
+                if (interval_id !== undefined) {
+                    start_timer();
+                }
+            }
+            request_step(window.abm.steps_per_frame.value);
+        }
+    }
+    let abm_render_pending = false;
+    let abm_last_render = 0;
+    function render_handler(now) {
+        // The closures of a run are dropped once another run is deployed
+        if (scenario_id !== window.abm.scenario_id) {
+            return;
+        }
+        // In the fast mode, draw at most 30 frames per second
+        if (now - abm_last_render < 1000 / 30) {
+            window.requestAnimationFrame(render_handler);
+        } else {
+            abm_render_pending = false;
+            abm_last_render = now;
+            rs_render_closure();
+        }
+    }
+    window.abm.worker.onmessage = function (event) {
+        let data = event.data;
+        if (data.type === "message") {
+            js_message_with_color(data.msg, data.error);
+        } else if (data.type === "saved") {
+            // An empty run means that saving failed
+            if (data.bytes.length !== 0) {
+                abm_download(new Blob([data.bytes]), "abm-run.abm");
             }
-            rs_step_closure();
+        } else if (data.type === "loaded") {
+            // The worker already runs the loaded model; the page shows its parameters and a new mirror
+            teardown();
+            window.abm.comparisons = [];
+            Object.assign(window.abm.params, data.params);
+            window.abm.scenario_id += 1;
+            window.abm.loading = true;
+            window.abm.rs_mod.rs_deploy_scenario();
+        } else if (data.type === "sweep") {
+            window.abm.sweep = data.bytes;
+            rs_sweep_closure(data.bytes);
+            if (!abm_running) {
+                rs_render_closure();
+            }
+        } else if (data.type === "bands" && data.id === scenario_id) {
+            // The replicate runs finish their time steps in the background, also while the run is paused
+            rs_bands_closure(data.bytes);
+            if (!abm_running) {
+                rs_render_closure();
+            }
+        } else if (data.type === "frame" && data.id === scenario_id) {
+            abm_show_comparisons();
+            // An empty frame means that the simulation failed
+            let running = data.frame.length !== 0 && rs_frame_closure(data.frame);
             abm_allow_step = true;
+            if (abm_running && window.abm.fast_switch.selected) {
+                if (interval_id !== undefined) {
+                    start_timer();
+                }
+                // The next time steps run while the page draws this frame
+                if (running) {
+                    request_step(window.abm.steps_per_frame.value);
+                }
+                if (!abm_render_pending) {
+                    abm_render_pending = true;
+                    window.requestAnimationFrame(render_handler);
+                }
+            } else {
+                if (abm_running && interval_id === undefined) {
+                    start_timer();
+                }
+                rs_render_closure();
+            }
+            if (!running && abm_running) {
+                // The model reached a stop condition
+                clearInterval(interval_id);
+                stop_impl();
+                abm_stop_recording();
+            }
+        }
+    };
+    let abm_resize_pending = false;
+    function resize_handler() {
+        // Redraw at most once per animation frame while the window is being resized
+        if (!abm_resize_pending) {
+            abm_resize_pending = true;
+            window.requestAnimationFrame(function () {
+                abm_resize_pending = false;
+                if (scenario_id === window.abm.scenario_id) {
+                    abm_resize_canvas();
+                    rs_render_closure();
+                }
+            });
         }
     }
-    step_handler(); // Shows the first frame of the sim
+    abm_resize_canvas();
+    window.addEventListener("resize", resize_handler, abm_passive_listener);
+    if (window.abm.loading) {
+        // Shows the saved time step of a loaded run without running another one
+        window.abm.loading = false;
+        request_step(0);
+    } else {
+        request_step(1); // Shows the first frame of the sim
+    }
     function stop_impl() {
         abm_running = false;
         start_stop_label.innerText = "play_arrow";
@@ -440,8 +981,10 @@ export function js_scenario(rs_step_closure) {
                 abm_running = true;
                 abm_frame_duration = frame_duration();
                 step_handler();
-                interval_id = setInterval(step_handler, abm_frame_duration);
-                start_stop_label.innerText = "pause";
+                if (abm_running) {
+                    start_timer();
+                    start_stop_label.innerText = "pause";
+                }
             }
             abm_allow_buttons = true;
         }
@@ -449,16 +992,116 @@ export function js_scenario(rs_step_closure) {
     start_stop.addEventListener("click", start_stop_handler, abm_passive_listener);
     let reset_button = js_get_id("abm-reset-button");
     let step_button = js_get_id("abm-step-button");
-    function reset_button_handler(event) {
+    let branch_button = js_get_id("abm-branch-button");
+    let timeline = js_get_id("abm-timeline");
+    function timeline_handler(event) {
+        js_get_id("abm-timeline-label").innerText = timeline.value;
+        rs_view_closure(timeline.valueAsNumber);
+    }
+    // Pauses the simulation, so that the user can change the parameters before the new run continues
+    function branch_button_handler(event) {
         if (abm_allow_buttons) {
             abm_allow_buttons = false;
-            start_stop.removeEventListener("click", start_stop_handler, abm_passive_listener);
-            reset_button.removeEventListener("click", reset_button_handler, abm_passive_listener);
             if (abm_running) {
-                start_stop_label.innerText = "play_arrow";
                 clearInterval(interval_id);
                 stop_impl();
             }
+            abm_stop_recording();
+            abm_allow_step = false;
+            window.abm.worker.postMessage({ type: "branch", id: scenario_id, time_step: timeline.valueAsNumber, params: window.abm.params });
+            abm_allow_buttons = true;
+        }
+    }
+    let compare_button = js_get_id("abm-compare-button");
+    let clear_comparisons_button = js_get_id("abm-clear-comparisons-button");
+    // Pins the current parameters in a comparison run; all runs restart together, with the same seed
+    function compare_button_handler(event) {
+        if (window.abm.comparisons.length >= abm_max_comparisons) {
+            js_error("Error: at most " + abm_max_comparisons + " runs can be compared with the current parameters.");
+        } else if (abm_allow_buttons) {
+            abm_allow_buttons = false;
+            teardown();
+            window.abm.comparisons.push(Object.assign({}, window.abm.params));
+            rs_deploy_scenario();
+            abm_allow_buttons = true;
+        }
+    }
+    function clear_comparisons_button_handler(event) {
+        if (abm_allow_buttons && window.abm.comparisons.length > 0) {
+            abm_allow_buttons = false;
+            teardown();
+            window.abm.comparisons = [];
+            rs_deploy_scenario();
+            abm_allow_buttons = true;
+        }
+    }
+    let save_button = js_get_id("abm-save-button");
+    let load_button = js_get_id("abm-load-button");
+    let load_input = js_get_id("abm-load-input");
+    function save_button_handler(event) {
+        window.abm.worker.postMessage({ type: "save", params: window.abm.params });
+    }
+    function load_button_handler(event) {
+        load_input.value = "";
+        load_input.click();
+    }
+    function load_input_handler(event) {
+        let file = load_input.files[0];
+        if (file !== undefined) {
+            file.arrayBuffer().then(function (buffer) {
+                window.abm.worker.postMessage({ type: "load", bytes: new Uint8Array(buffer), params: window.abm.params }, [buffer]);
+            }, console.error);
+        }
+    }
+    let sweep_button = js_get_id("abm-sweep-button");
+    // The worker runs the sweep one grid point at a time; the live run pauses, but it can continue between grid points
+    function sweep_button_handler(event) {
+        if (!window.abm.sweep_x_range.valid || (window.abm.params.sweep_y !== "none" && !window.abm.sweep_y_range.valid)) {
+            js_error("Error: please correct the ranges of the sweep.");
+        } else if (abm_allow_buttons) {
+            abm_allow_buttons = false;
+            if (abm_running) {
+                clearInterval(interval_id);
+                stop_impl();
+            }
+            abm_stop_recording();
+            window.abm.worker.postMessage({ type: "sweep", params: window.abm.params });
+            abm_allow_buttons = true;
+        }
+    }
+    timeline.addEventListener("input", timeline_handler, abm_passive_listener);
+    sweep_button.addEventListener("click", sweep_button_handler, abm_passive_listener);
+    branch_button.addEventListener("click", branch_button_handler, abm_passive_listener);
+    compare_button.addEventListener("click", compare_button_handler, abm_passive_listener);
+    clear_comparisons_button.addEventListener("click", clear_comparisons_button_handler, abm_passive_listener);
+    save_button.addEventListener("click", save_button_handler, abm_passive_listener);
+    load_button.addEventListener("click", load_button_handler, abm_passive_listener);
+    load_input.addEventListener("change", load_input_handler, abm_passive_listener);
+    // Stops this run and removes its listeners before another one is deployed
+    function teardown() {
+        start_stop.removeEventListener("click", start_stop_handler, abm_passive_listener);
+        reset_button.removeEventListener("click", reset_button_handler, abm_passive_listener);
+        step_button.removeEventListener("click", step_button_handler, abm_passive_listener);
+        branch_button.removeEventListener("click", branch_button_handler, abm_passive_listener);
+        compare_button.removeEventListener("click", compare_button_handler, abm_passive_listener);
+        clear_comparisons_button.removeEventListener("click", clear_comparisons_button_handler, abm_passive_listener);
+        save_button.removeEventListener("click", save_button_handler, abm_passive_listener);
+        load_button.removeEventListener("click", load_button_handler, abm_passive_listener);
+        load_input.removeEventListener("change", load_input_handler, abm_passive_listener);
+        sweep_button.removeEventListener("click", sweep_button_handler, abm_passive_listener);
+        timeline.removeEventListener("input", timeline_handler, abm_passive_listener);
+        window.removeEventListener("resize", resize_handler, abm_passive_listener);
+        if (abm_running) {
+            start_stop_label.innerText = "play_arrow";
+            clearInterval(interval_id);
+            stop_impl();
+        }
+        abm_stop_recording();
+    }
+    function reset_button_handler(event) {
+        if (abm_allow_buttons) {
+            abm_allow_buttons = false;
+            teardown();
             // Restart simulation here
             rs_deploy_scenario();
             abm_allow_buttons = true;
@@ -483,12 +1126,84 @@ export function js_scenario(rs_step_closure) {
 function js_init() {
     {
         window.abm = {};
+        window.abm.params = abm_params();
+        // Hooks for src/bridge.js
+        window.abm.scenario = js_scenario;
+        window.abm.frame = js_frame;
+        window.abm.toggle_cell = js_toggle_cell;
+        window.abm.timeline = js_timeline;
+        window.abm.message = js_message_with_color;
+        // The simulation runs in a worker so that the controls stay responsive
+        window.abm.worker = new Worker(new URL("./worker.js", import.meta.url));
+        window.abm.scenario_id = 0;
+        window.abm.comparisons = [];
         window.abm.nAgents0 = new JsSliderValue(1, 1, 2000, 1, 2000, 1, true, "abm-n-agents");
         window.abm.worldLength = new JsSliderValue(10, 2, 200, 2, 200, 1, false, "abm-world-length");
+        window.abm.replicates = new JsSliderValue(0, 0, 100, 0, 100, 1, false, "abm-replicates");
         window.abm.fps = new JsSliderValue(1, 0.25, 100, 0.25, 100, 0.25, false, "abm-fps");
+        window.abm.steps_per_frame = new JsSliderValue(1, 1, 100000, 1, 1000, 1, true, "abm-steps-per-frame");
+        window.abm.fast_switch = new MDCSwitch(js_get_id("abm-fast-switch"));
         window.abm.dark_figures_switch = new MDCSwitch(js_get_id("abm-dark-mode-switch"));
         window.abm.infection_probability = new JsSliderValue(0.5, 0, 1, 0, 1, 0.01, false, "abm-infection-probability");
-        // window.abm.example_range = new JsMinMax(10, 20, 1, 100, 1, "abm-example-range");
+        window.abm.birth_rate = new JsSliderValue(0.01, 0, 1, 0, 0.2, 0.001, false, "abm-birth-rate");
+        window.abm.immigration = new JsSliderValue(5, 0, 1000, 0, 100, 1, false, "abm-immigration");
+        window.abm.carrying_capacity = new JsSliderValue(1000, 1, 30000, 10, 5000, 1, true, "abm-carrying-capacity");
+        window.abm.awareness = new JsSliderValue(0, 0, 100, 0, 20, 0.1, false, "abm-awareness");
+        window.abm.compliance = new JsSliderValue(0.5, 0, 1, 0, 1, 0.01, false, "abm-compliance");
+        window.abm.detection_probability = new JsSliderValue(0, 0, 1, 0, 1, 0.01, false, "abm-detection-probability");
+        window.abm.tracing_capacity = new JsSliderValue(10, 0, 10000, 0, 200, 1, false, "abm-tracing-capacity");
+        window.abm.quarantine_duration = new JsSliderValue(14, 1, 1000, 1, 50, 1, false, "abm-quarantine-duration");
+        window.abm.max_steps = new JsSliderValue(0, 0, 1000000, 0, 2000, 1, false, "abm-max-steps");
+        window.abm.stop_disease_extinction_switch = new MDCSwitch(js_get_id("abm-stop-disease-extinction-switch"));
+        window.abm.stop_disease_extinction_switch.selected = true;
+        window.abm.stop_population_extinction_switch = new MDCSwitch(js_get_id("abm-stop-population-extinction-switch"));
+        window.abm.stop_population_extinction_switch.selected = true;
+        window.abm.prevalence_threshold = new JsSliderValue(0, 0, 1, 0, 1, 0.01, false, "abm-prevalence-threshold");
+        window.abm.dashboard = new MDCTextField(js_get_id("abm-dashboard-text"));
+        // The dashboard layout persists across visits; an empty layout means the default one
+        window.abm.dashboard.value = window.localStorage.getItem("abm-dashboard") || "";
+        js_get_id("abm-dashboard-text-input").addEventListener("change", function () {
+            if (window.abm.dashboard.value.trim() === "") {
+                window.localStorage.removeItem("abm-dashboard");
+            } else {
+                window.localStorage.setItem("abm-dashboard", window.abm.dashboard.value);
+            }
+            if (window.abm.rs_render !== undefined) {
+                window.abm.rs_render();
+            }
+        }, abm_passive_listener);
+        window.abm.proportional_switch = new MDCSwitch(js_get_id("abm-proportional-switch"));
+        js_get_id("abm-palette").addEventListener("change", function () {
+            js_get_id("abm-custom-colors").hidden = window.abm.params.palette !== "custom";
+        }, abm_passive_listener);
+        // Figure parameters redraw the figures without running a time step
+        for (const el of [js_get_id("abm-dark-mode-switch"), js_get_id("abm-palette"), js_get_id("abm-susceptible-color"),
+            js_get_id("abm-infected-color"), js_get_id("abm-histogram-mode"), js_get_id("abm-agent-series"), js_get_id("abm-net-series"), js_get_id("abm-proportional-switch")]) {
+            el.addEventListener(el.tagName === "BUTTON" ? "click" : "change", function () {
+                if (window.abm.rs_render !== undefined) {
+                    window.abm.rs_render();
+                }
+            }, abm_passive_listener);
+        }
+        window.abm.graph_nodes = new JsSliderValue(300, 0, 5000, 0, 1000, 1, false, "abm-graph-nodes");
+        window.abm.net_metrics_interval = new JsSliderValue(1, 0, 10000, 0, 100, 1, false, "abm-net-metrics-interval");
+        window.abm.record_switch = new MDCSwitch(js_get_id("abm-record-switch"));
+        window.abm.record_from = new JsSliderValue(0, 0, 1000000, 0, 1000, 1, false, "abm-record-from");
+        window.abm.record_to = new JsSliderValue(100, 0, 1000000, 0, 1000, 1, false, "abm-record-to");
+        window.abm.sweep_x_range = new JsMinMax(0, 1, 0, 1000000, 0.001, "abm-sweep-x-range");
+        window.abm.sweep_x_steps = new JsSliderValue(10, 2, 20, 2, 20, 1, false, "abm-sweep-x-steps");
+        window.abm.sweep_y_range = new JsMinMax(0, 1, 0, 1000000, 0.001, "abm-sweep-y-range");
+        window.abm.sweep_y_steps = new JsSliderValue(10, 2, 20, 2, 20, 1, false, "abm-sweep-y-steps");
+        window.abm.sweep_runs = new JsSliderValue(3, 1, 20, 1, 20, 1, false, "abm-sweep-runs");
+        window.abm.sweep_time_steps = new JsSliderValue(200, 1, 10000, 1, 1000, 1, true, "abm-sweep-time-steps");
+        for (const axis of ["x", "y"]) {
+            js_get_id("abm-sweep-" + axis).addEventListener("change", function (event) {
+                let range = abm_sweep_ranges[event.target.value];
+                if (range !== undefined) {
+                    window.abm["sweep_" + axis + "_range"].set(range[0], range[1]);
+                }
+            }, abm_passive_listener);
+        }
 
         for (let el of document.getElementsByClassName("mdc-fab")) {
             const fabRipple = new MDCRipple(el);
@@ -497,6 +1212,26 @@ function js_init() {
             const iconButtonRipple = new MDCRipple(el);
             iconButtonRipple.unbounded = true;
         }
+        let abm_canvas = js_get_id("abm-canvas");
+        abm_canvas.addEventListener("pointermove", event => abm_canvas_pointer(event, false), abm_passive_listener);
+        abm_canvas.addEventListener("click", event => abm_canvas_pointer(event, event.shiftKey), abm_passive_listener);
+        abm_canvas.addEventListener("pointerleave", event => js_get_id("abm-tooltip").style.display = "none", abm_passive_listener);
+        abm_canvas.addEventListener("pointermove", abm_chart_pointer_move, abm_passive_listener);
+        abm_canvas.addEventListener("pointerdown", abm_chart_pointer_down, abm_passive_listener);
+        abm_canvas.addEventListener("pointerup", abm_chart_pointer_up, abm_passive_listener);
+        abm_canvas.addEventListener("pointerleave", function (event) {
+            window.abm.chart_drag = undefined;
+            abm_clear_overlay();
+        }, abm_passive_listener);
+        abm_canvas.addEventListener("wheel", abm_chart_wheel, abm_passive_listener);
+        abm_canvas.addEventListener("dblclick", abm_chart_double_click, abm_passive_listener);
+        js_get_id("abm-svg-button").addEventListener("click", abm_svg_button_handler, abm_passive_listener);
+        js_get_id("abm-png-button").addEventListener("click", abm_png_button_handler, abm_passive_listener);
+        js_get_id("abm-events-button").addEventListener("click", abm_events_button_handler, abm_passive_listener);
+        // Sliders inside collapsed sections have no size until the section opens
+        for (let el of document.getElementsByTagName("details")) {
+            el.addEventListener("toggle", abm_layout, abm_passive_listener);
+        }
         // Switches should be handled individually
         // for (let el of document.getElementsByClassName("mdc-switch")) {
         //     const switchControl = new MDCSwitch(el);
@@ -0,0 +1,53 @@
+/* This file is part of wasm-agent-based-models:
+   Reliable and efficient agent-based models in Rust and WebAssembly
+
+    Copyright 2020 Fabio A. Correa Duran facorread@gmail.com
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+//! Continues a run saved in the browser, without the browser.
+//!
+//! Usage: `cargo run --release --bin runner -- <saved run> <time steps> <output file>`
+//!
+//! The output file is a saved run too, so the browser can load it to show the figures.
+
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 4 {
+        eprintln!("Usage: {} <saved run> <time steps> <output file>", args[0]);
+        return ExitCode::FAILURE;
+    }
+    let steps: u32 = match args[2].parse() {
+        Ok(steps) => steps,
+        Err(e) => {
+            eprintln!("Error: time steps = {}: {}", args[2], e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let result = std::fs::read(&args[1])
+        .map_err(|e| format!("cannot read {}: {}", args[1], e))
+        .and_then(|saved| wasm_agent_based_models::continue_saved_run(&saved, steps))
+        .and_then(|saved| {
+            std::fs::write(&args[3], saved).map_err(|e| format!("cannot write {}: {}", args[3], e))
+        });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,224 @@
+/* This file is part of wasm-agent-based-models:
+   Reliable and efficient agent-based models in Rust and WebAssembly
+
+    Copyright 2020 Fabio A. Correa Duran facorread@gmail.com
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+"use strict";
+
+// The wasm module imports these functions on the page and in the simulation worker.
+// Parameters come from globalThis.abm.params; the page posts them to the worker with each message.
+// The remaining functions call the hooks that app.js and worker.js install in globalThis.abm.
+
+export function js_n0() {
+    return globalThis.abm.params.n0;
+}
+
+export function js_world_length() {
+    return globalThis.abm.params.world_length;
+}
+
+export function js_replicates() {
+    return globalThis.abm.params.replicates;
+}
+
+export function js_infection_probability() {
+    return globalThis.abm.params.infection_probability;
+}
+
+export function js_demography() {
+    return globalThis.abm.params.demography;
+}
+
+export function js_birth_rate() {
+    return globalThis.abm.params.birth_rate;
+}
+
+export function js_immigration() {
+    return globalThis.abm.params.immigration;
+}
+
+export function js_carrying_capacity() {
+    return globalThis.abm.params.carrying_capacity;
+}
+
+export function js_risk_perception() {
+    return globalThis.abm.params.risk_perception;
+}
+
+export function js_awareness() {
+    return globalThis.abm.params.awareness;
+}
+
+export function js_compliance() {
+    return globalThis.abm.params.compliance;
+}
+
+export function js_detection_probability() {
+    return globalThis.abm.params.detection_probability;
+}
+
+export function js_tracing_capacity() {
+    return globalThis.abm.params.tracing_capacity;
+}
+
+export function js_quarantine_duration() {
+    return globalThis.abm.params.quarantine_duration;
+}
+
+export function js_max_steps() {
+    return globalThis.abm.params.max_steps;
+}
+
+export function js_stop_on_disease_extinction() {
+    return globalThis.abm.params.stop_on_disease_extinction;
+}
+
+export function js_stop_on_population_extinction() {
+    return globalThis.abm.params.stop_on_population_extinction;
+}
+
+export function js_prevalence_threshold() {
+    return globalThis.abm.params.prevalence_threshold;
+}
+
+export function js_dashboard() {
+    return globalThis.abm.params.dashboard;
+}
+
+export function js_agent_series() {
+    return globalThis.abm.params.agent_series;
+}
+
+export function js_proportional() {
+    return globalThis.abm.params.proportional;
+}
+
+export function js_histogram_mode() {
+    return globalThis.abm.params.histogram_mode;
+}
+
+export function js_palette() {
+    return globalThis.abm.params.palette;
+}
+
+export function js_susceptible_color() {
+    return globalThis.abm.params.susceptible_color;
+}
+
+export function js_infected_color() {
+    return globalThis.abm.params.infected_color;
+}
+
+export function js_graph_nodes() {
+    return globalThis.abm.params.graph_nodes;
+}
+
+export function js_net_metrics_interval() {
+    return globalThis.abm.params.net_metrics_interval;
+}
+
+export function js_net_series() {
+    return globalThis.abm.params.net_series;
+}
+
+export function js_dark_figures() {
+    return globalThis.abm.params.dark_figures;
+}
+
+export function js_sweep_x() {
+    return globalThis.abm.params.sweep_x;
+}
+
+export function js_sweep_x_min() {
+    return globalThis.abm.params.sweep_x_min;
+}
+
+export function js_sweep_x_max() {
+    return globalThis.abm.params.sweep_x_max;
+}
+
+export function js_sweep_x_steps() {
+    return globalThis.abm.params.sweep_x_steps;
+}
+
+export function js_sweep_y() {
+    return globalThis.abm.params.sweep_y;
+}
+
+export function js_sweep_y_min() {
+    return globalThis.abm.params.sweep_y_min;
+}
+
+export function js_sweep_y_max() {
+    return globalThis.abm.params.sweep_y_max;
+}
+
+export function js_sweep_y_steps() {
+    return globalThis.abm.params.sweep_y_steps;
+}
+
+export function js_sweep_outcome() {
+    return globalThis.abm.params.sweep_outcome;
+}
+
+export function js_sweep_runs() {
+    return globalThis.abm.params.sweep_runs;
+}
+
+export function js_sweep_time_steps() {
+    return globalThis.abm.params.sweep_time_steps;
+}
+
+export function js_scenario(rs_frame_closure, rs_render_closure, rs_svg_closure, rs_cell_closure, rs_hover_closure, rs_zoom_closure, rs_view_closure, rs_sweep_closure, rs_events_closure, rs_bands_closure) {
+    globalThis.abm.scenario(rs_frame_closure, rs_render_closure, rs_svg_closure, rs_cell_closure, rs_hover_closure, rs_zoom_closure, rs_view_closure, rs_sweep_closure, rs_events_closure, rs_bands_closure);
+}
+
+export function js_frame(time_step) {
+    globalThis.abm.frame(time_step);
+}
+
+export function js_toggle_cell(idx) {
+    globalThis.abm.toggle_cell(idx);
+}
+
+export function js_timeline(latest_time_step) {
+    globalThis.abm.timeline(latest_time_step);
+}
+
+export function js_sweep(bytes) {
+    globalThis.abm.sweep(bytes);
+}
+
+export function js_sweep_start(rs_sweep_point_closure) {
+    globalThis.abm.sweep_start(rs_sweep_point_closure);
+}
+
+export function js_worker_scenario(rs_step_closure, rs_toggle_cell_closure, rs_branch_closure, rs_save_closure, rs_compare_closure, rs_replicates_closure) {
+    globalThis.abm.rs_step = rs_step_closure;
+    globalThis.abm.rs_toggle_cell = rs_toggle_cell_closure;
+    globalThis.abm.rs_branch = rs_branch_closure;
+    globalThis.abm.rs_save = rs_save_closure;
+    globalThis.abm.rs_compare = rs_compare_closure;
+    globalThis.abm.rs_replicates = rs_replicates_closure;
+}
+
+export function js_message(msg) {
+    globalThis.abm.message(msg, false);
+}
+
+export function js_error(msg) {
+    globalThis.abm.message(msg, true);
+}
@@ -16,23 +16,106 @@
    limitations under the License.
 */
 
+use std::cell::RefCell;
+use std::rc::Rc;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 
-#[wasm_bindgen(module = "/src/app.js")]
+// The page and the simulation worker both load this module; see src/bridge.js
+#[wasm_bindgen(module = "/src/bridge.js")]
 extern "C" {
     // This project used to have a pub type JsAbmParams. Unfortunately, limitations on wasm-bindgen make this impractical. See
     // https://github.com/rustwasm/wasm-bindgen/issues/1187
     // https://docs.rs/wasm-bindgen/0.2.69/wasm_bindgen/closure/struct.Closure.html
     fn js_n0() -> u32;
     fn js_world_length() -> i32;
+    fn js_replicates() -> u32;
     fn js_infection_probability() -> f64;
+    fn js_demography() -> String;
+    fn js_birth_rate() -> f64;
+    fn js_immigration() -> u32;
+    fn js_carrying_capacity() -> u32;
+    fn js_risk_perception() -> String;
+    fn js_awareness() -> f64;
+    fn js_compliance() -> f64;
+    fn js_detection_probability() -> f64;
+    fn js_tracing_capacity() -> u32;
+    fn js_quarantine_duration() -> u32;
+    fn js_max_steps() -> u32;
+    fn js_stop_on_disease_extinction() -> bool;
+    fn js_stop_on_population_extinction() -> bool;
+    fn js_prevalence_threshold() -> f64;
+    fn js_dashboard() -> String;
+    fn js_agent_series() -> String;
+    fn js_proportional() -> bool;
+    fn js_histogram_mode() -> String;
+    fn js_palette() -> String;
+    fn js_susceptible_color() -> String;
+    fn js_infected_color() -> String;
+    fn js_graph_nodes() -> u32;
+    fn js_net_metrics_interval() -> u32;
+    fn js_net_series() -> String;
     fn js_dark_figures() -> bool;
-    fn js_scenario(rs_step_closure: &::js_sys::Function);
+    fn js_sweep_x() -> String;
+    fn js_sweep_x_min() -> f64;
+    fn js_sweep_x_max() -> f64;
+    fn js_sweep_x_steps() -> u32;
+    fn js_sweep_y() -> String;
+    fn js_sweep_y_min() -> f64;
+    fn js_sweep_y_max() -> f64;
+    fn js_sweep_y_steps() -> u32;
+    fn js_sweep_outcome() -> String;
+    fn js_sweep_runs() -> u32;
+    fn js_sweep_time_steps() -> u32;
+    fn js_scenario(
+        rs_frame_closure: &::js_sys::Function,
+        rs_render_closure: &::js_sys::Function,
+        rs_svg_closure: &::js_sys::Function,
+        rs_cell_closure: &::js_sys::Function,
+        rs_hover_closure: &::js_sys::Function,
+        rs_zoom_closure: &::js_sys::Function,
+        rs_view_closure: &::js_sys::Function,
+        rs_sweep_closure: &::js_sys::Function,
+        rs_events_closure: &::js_sys::Function,
+        rs_bands_closure: &::js_sys::Function,
+    );
+    fn js_frame(time_step: u32);
+    fn js_toggle_cell(idx: u32);
+    fn js_timeline(latest_time_step: u32);
+    fn js_sweep_start(rs_sweep_point_closure: &::js_sys::Function);
+    fn js_worker_scenario(
+        rs_step_closure: &::js_sys::Function,
+        rs_toggle_cell_closure: &::js_sys::Function,
+        rs_branch_closure: &::js_sys::Function,
+        rs_save_closure: &::js_sys::Function,
+        rs_compare_closure: &::js_sys::Function,
+        rs_replicates_closure: &::js_sys::Function,
+    );
+}
+
+// The native runner prints messages instead
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(module = "/src/bridge.js")]
+extern "C" {
     fn js_message(msg: &str);
     fn js_error(msg: &str);
+    fn js_sweep(bytes: std::vec::Vec<u8>);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn js_message(msg: &str) {
+    println!("{}", msg);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn js_error(msg: &str) {
+    eprintln!("{}", msg);
 }
 
+/// Only the page draws the sweeps
+#[cfg(all(not(target_arch = "wasm32"), feature = "graphics"))]
+fn js_sweep(_bytes: std::vec::Vec<u8>) {}
+
 // When the `dlmalloc` feature is enabled, use `dlmalloc` as the global allocator.
 #[cfg(feature = "dlmalloc")]
 #[global_allocator]
@@ -43,12 +126,23 @@ static ALLOC: dlmalloc::GlobalDlmalloc = dlmalloc::GlobalDlmalloc;
 
 ///! This software uses the Entity-Component-System (ECS) architecture and other principles discussed at https://kyren.github.io/2018/09/14/rustconf-talk.html
 #[cfg(feature = "graphics")]
+use plotters::coord::{
+    cartesian::Cartesian2d,
+    types::{RangedCoordf64, RangedCoordu32},
+};
+#[cfg(feature = "graphics")]
 use plotters::prelude::*;
 #[cfg(feature = "net")]
 use rand::distributions::weighted::{WeightedError, WeightedIndex};
 use rand::distributions::{Bernoulli, Distribution};
+#[cfg(any(feature = "contact-tracing", feature = "net-graphics"))]
+use rand::seq::SliceRandom;
+#[cfg(feature = "net-graphics")]
+use rand::Rng;
+use rand::SeedableRng;
 #[cfg(feature = "landscape")]
 use rand_distr::Normal;
+use serde::{Deserialize, Serialize};
 use slotmap::{SecondaryMap, SlotMap};
 #[cfg(feature = "net-graphics")]
 use std::collections::{BTreeMap, BTreeSet};
@@ -59,20 +153,217 @@ use std::io::Write as IoWrite; // See https://doc.rust-lang.org/std/macro.writel
 use wrapping_coords2d::WrappingCoords2d;
 
 // Model properties
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 enum Health {
     S,
     I,
 }
 
+/// Demographic regime: how new agents emerge at the end of each time step
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+enum Demography {
+    /// Each susceptible agent gives birth with probability `birth_rate`
+    #[default]
+    SusceptibleBirths,
+    /// A constant number of susceptible agents immigrates each time step
+    Immigration,
+    /// Each agent gives birth with probability `birth_rate * (1 - n / carrying_capacity)`
+    Logistic,
+    /// Each living agent gives birth with probability `birth_rate`
+    AllBirths,
+}
+
+impl Demography {
+    /// Parses the value of the demography selector in index.html
+    fn from_js(value: &str) -> Self {
+        match value {
+            "immigration" => Demography::Immigration,
+            "logistic" => Demography::Logistic,
+            "all-births" => Demography::AllBirths,
+            _ => Demography::SusceptibleBirths,
+        }
+    }
+
+    /// Value of the demography selector in index.html
+    fn to_js(self) -> &'static str {
+        match self {
+            Demography::SusceptibleBirths => "susceptible-births",
+            Demography::Immigration => "immigration",
+            Demography::Logistic => "logistic",
+            Demography::AllBirths => "all-births",
+        }
+    }
+}
+
+/// Source of information that agents use to perceive the risk of infection
+#[cfg(feature = "behavior")]
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+enum RiskPerception {
+    /// Infected fraction among network neighbours; agents without neighbours use the global prevalence
+    #[default]
+    Neighbours,
+    /// Global prevalence `i / n`
+    Global,
+}
+
+#[cfg(feature = "behavior")]
+impl RiskPerception {
+    /// Parses the value of the risk perception selector in index.html
+    fn from_js(value: &str) -> Self {
+        match value {
+            "global" => RiskPerception::Global,
+            _ => RiskPerception::Neighbours,
+        }
+    }
+
+    /// Value of the risk perception selector in index.html
+    fn to_js(self) -> &'static str {
+        match self {
+            RiskPerception::Neighbours => "neighbours",
+            RiskPerception::Global => "global",
+        }
+    }
+}
+
+/// Reason to terminate a run of the model
+#[derive(Clone, Copy, PartialEq)]
+enum StopReason {
+    /// The run reached the maximum number of time steps
+    MaxSteps,
+    /// No agents or cells are infected
+    DiseaseExtinction,
+    /// No agents are alive
+    PopulationExtinction,
+    /// The prevalence `i / n` reached the threshold
+    PrevalenceThreshold,
+}
+
+impl StopReason {
+    /// Describes the reason to the user
+    fn description(self) -> &'static str {
+        match self {
+            StopReason::MaxSteps => "the maximum number of time steps was reached",
+            StopReason::DiseaseExtinction => "the disease went extinct",
+            StopReason::PopulationExtinction => "the population went extinct",
+            StopReason::PrevalenceThreshold => "the prevalence reached the threshold",
+        }
+    }
+}
+
 // Housekeeping
 slotmap::new_key_type! {
     struct AgentKey;
     struct LinkKey;
 }
 
+/// Infector of an agent, which also determines the route of the infection
+#[cfg(feature = "infection-events")]
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum Source {
+    /// Initial infection during the first time step
+    Seed,
+    /// Infectious neighbour in the social network
+    #[cfg(feature = "net")]
+    Neighbor(AgentKey),
+    /// Infectious cell of the landscape that the agent visited
+    #[cfg(feature = "landscape")]
+    Cell(u32),
+}
+
+#[cfg(feature = "infection-events")]
+impl Source {
+    fn route(self) -> &'static str {
+        match self {
+            Source::Seed => "seed",
+            #[cfg(feature = "net")]
+            Source::Neighbor(_) => "network",
+            #[cfg(feature = "landscape")]
+            Source::Cell(_) => "landscape",
+        }
+    }
+}
+
+/// Infection of an agent
+#[cfg(feature = "infection-events")]
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct InfectionEvent {
+    /// Time step during which the agent got infected; it is infected from the next time step on
+    time_step: u32,
+    /// Infected agent
+    infectee: AgentKey,
+    source: Source,
+}
+
+/// Identifier of an agent in exports; it is unique during a run, unlike the slot of the agent
+#[cfg(feature = "infection-events")]
+fn agent_id(key: AgentKey) -> u64 {
+    slotmap::Key::data(&key).as_ffi()
+}
+
+/// Maximum number of infections in the transmission tree panel
+#[cfg(all(feature = "graphics", feature = "infection-events"))]
+const MAX_TREE_EVENTS: usize = 500;
+
+/// Who infected whom, from a chronological list of infection events.
+/// Infections through cells start new chains, because cells do not record their infectors.
+#[cfg(feature = "infection-events")]
+struct TransmissionTree {
+    /// Event that infected the infector of each event, if the list has it
+    parents: std::vec::Vec<Option<usize>>,
+    /// Number of agents that each infected agent infected before it was infected again
+    secondary_cases: std::vec::Vec<u32>,
+}
+
+#[cfg(feature = "infection-events")]
+impl TransmissionTree {
+    fn new(events: &[InfectionEvent]) -> Self {
+        let mut parents = std::vec::Vec::with_capacity(events.len());
+        let mut secondary_cases = vec![0; events.len()];
+        // Latest infection of each agent
+        let mut latest: SecondaryMap<AgentKey, usize> = SecondaryMap::new();
+        for (idx, event) in events.iter().enumerate() {
+            let parent = match event.source {
+                #[cfg(feature = "net")]
+                Source::Neighbor(infector) => latest.get(infector).copied(),
+                _ => None,
+            };
+            if let Some(parent) = parent {
+                secondary_cases[parent] += 1;
+            }
+            parents.push(parent);
+            latest.insert(event.infectee, idx);
+        }
+        TransmissionTree {
+            parents,
+            secondary_cases,
+        }
+    }
+
+    /// Rows of the events in a drawing of the tree, so that each chain of infections occupies consecutive rows
+    #[cfg(feature = "graphics")]
+    fn rows(&self) -> std::vec::Vec<usize> {
+        let mut children = vec![std::vec::Vec::new(); self.parents.len()];
+        let mut stack = std::vec::Vec::new();
+        for (idx, parent) in self.parents.iter().enumerate().rev() {
+            match parent {
+                Some(parent) => children[*parent].push(idx),
+                None => stack.push(idx),
+            }
+        }
+        let mut rows = vec![0; self.parents.len()];
+        let mut row = 0;
+        while let Some(idx) = stack.pop() {
+            rows[idx] = row;
+            row += 1;
+            // Children were pushed in reverse order, so the earliest one comes next
+            stack.extend(children[idx].iter().copied());
+        }
+        rows
+    }
+}
+
 /// Simulation results for a time step
-#[derive(Clone, Default)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 struct TimeStepResults {
     /// Time step
     time_step: u32,
@@ -85,30 +376,511 @@ struct TimeStepResults {
     /// Infected
     #[cfg(feature = "agent-metrics")]
     i: u32,
+    /// Self-isolating susceptibles
+    #[cfg(all(feature = "agent-metrics", feature = "behavior"))]
+    a: u32,
+    /// New infections during the previous time step
+    #[cfg(feature = "agent-metrics")]
+    incidence: u32,
+    /// Deaths during the previous time step
+    #[cfg(feature = "agent-metrics")]
+    deaths: u32,
+    /// Births and immigrants during the previous time step
+    #[cfg(feature = "agent-metrics")]
+    births: u32,
+    /// Infected agents detected by testing
+    #[cfg(all(feature = "agent-metrics", feature = "contact-tracing"))]
+    t_d: u32,
+    /// Agents in quarantine
+    #[cfg(all(feature = "agent-metrics", feature = "contact-tracing"))]
+    t_q: u32,
+    /// Missed cases: infected agents outside quarantine
+    #[cfg(all(feature = "agent-metrics", feature = "contact-tracing"))]
+    t_m: u32,
     /// Maximum network degree of susceptibles
     #[cfg(feature = "net-metrics")]
     d_s: i32,
     /// Maximum network degree of infectious
     #[cfg(feature = "net-metrics")]
     d_i: i32,
+    /// Structure of the network, every `net_metrics_interval` time steps
+    #[cfg(feature = "net-metrics")]
+    net: Option<NetMetrics>,
     /// Infected cells
     #[cfg(feature = "landscape-metrics")]
     c_i: u32,
     /// Histogram of network degrees
     #[cfg(feature = "net-graphics")]
     degree_histogram: BTreeMap<i32, u32>,
-    /// Health status
+    /// Health status of each cell; empty once the result is older than `MAX_LANDSCAPE_HISTORY` time steps
     #[cfg(feature = "landscape-graphics")]
     cell_health: Vec<Health>,
 }
 
+/// Structure of the social network in a time step
+#[cfg(feature = "net-metrics")]
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+struct NetMetrics {
+    /// Mean number of links per agent
+    mean_degree: f64,
+    /// Mean number of links per susceptible agent
+    mean_degree_s: f64,
+    /// Mean number of links per infected agent
+    mean_degree_i: f64,
+    /// Average local clustering coefficient; agents with fewer than two links count as 0
+    clustering: f64,
+    /// Correlation between the degrees at both ends of each link; 0 when undefined
+    assortativity: f64,
+    /// Number of connected components, including isolated agents
+    components: u32,
+    /// Number of agents in the largest connected component
+    largest_component: u32,
+}
+
+#[cfg(feature = "net-metrics")]
+impl NetMetrics {
+    /// Measures the network in time proportional to the agents, the links, and the triangles
+    fn measure(
+        health: &SlotMap<AgentKey, Health>,
+        links: &SlotMap<LinkKey, (AgentKey, AgentKey)>,
+    ) -> Self {
+        let mut metrics = NetMetrics::default();
+        let n = health.len();
+        if n == 0 {
+            return metrics;
+        }
+        let mut idx_map = SecondaryMap::with_capacity(health.capacity());
+        health.keys().enumerate().for_each(|(idx, k)| {
+            idx_map.insert(k, idx);
+        });
+        // Sorted neighbours of each agent, without repeated links
+        let mut neighbors = vec![std::vec::Vec::new(); n];
+        links.values().for_each(|&(key0, key1)| {
+            if let (Some(&idx0), Some(&idx1)) = (idx_map.get(key0), idx_map.get(key1)) {
+                if idx0 != idx1 {
+                    neighbors[idx0].push(idx1);
+                    neighbors[idx1].push(idx0);
+                }
+            }
+        });
+        for list in neighbors.iter_mut() {
+            list.sort_unstable();
+            list.dedup();
+        }
+        let degree = |idx: usize| neighbors[idx].len() as f64;
+        let (mut sum_s, mut n_s, mut sum_i, mut n_i) = (0.0, 0, 0.0, 0);
+        for (idx, h) in health.values().enumerate() {
+            match h {
+                Health::S => {
+                    sum_s += degree(idx);
+                    n_s += 1;
+                }
+                Health::I => {
+                    sum_i += degree(idx);
+                    n_i += 1;
+                }
+            }
+        }
+        metrics.mean_degree = (sum_s + sum_i) / n as f64;
+        metrics.mean_degree_s = if n_s > 0 { sum_s / n_s as f64 } else { 0.0 };
+        metrics.mean_degree_i = if n_i > 0 { sum_i / n_i as f64 } else { 0.0 };
+        // Each triangle u < v < w is found once, from its link (u, v)
+        let mut triangles = vec![0u32; n];
+        let (mut m, mut sum_jk, mut sum_mean, mut sum_square) = (0.0, 0.0, 0.0, 0.0);
+        for u in 0..n {
+            for &v in neighbors[u].iter().filter(|&&v| v > u) {
+                let (j, k) = (degree(u), degree(v));
+                m += 1.0;
+                sum_jk += j * k;
+                sum_mean += 0.5 * (j + k);
+                sum_square += 0.5 * (j * j + k * k);
+                let (mut a, mut b) = (
+                    neighbors[u].iter().peekable(),
+                    neighbors[v].iter().peekable(),
+                );
+                while let (Some(&&x), Some(&&y)) = (a.peek(), b.peek()) {
+                    if x < y {
+                        a.next();
+                    } else if y < x {
+                        b.next();
+                    } else {
+                        if x > v {
+                            triangles[u] += 1;
+                            triangles[v] += 1;
+                            triangles[x] += 1;
+                        }
+                        a.next();
+                        b.next();
+                    }
+                }
+            }
+        }
+        metrics.clustering = (0..n)
+            .filter(|&idx| neighbors[idx].len() >= 2)
+            .map(|idx| 2.0 * triangles[idx] as f64 / (degree(idx) * (degree(idx) - 1.0)))
+            .sum::<f64>()
+            / n as f64;
+        if m > 0.0 {
+            let mean = sum_mean / m;
+            let variance = sum_square / m - mean * mean;
+            if variance > 1e-12 {
+                metrics.assortativity = (sum_jk / m - mean * mean) / variance;
+            }
+        }
+        // Connected components, by depth-first search
+        let mut visited = vec![false; n];
+        let mut stack = std::vec::Vec::new();
+        for start in 0..n {
+            if visited[start] {
+                continue;
+            }
+            visited[start] = true;
+            stack.push(start);
+            let mut size = 0;
+            while let Some(idx) = stack.pop() {
+                size += 1;
+                for &neighbor in &neighbors[idx] {
+                    if !visited[neighbor] {
+                        visited[neighbor] = true;
+                        stack.push(neighbor);
+                    }
+                }
+            }
+            metrics.components += 1;
+            metrics.largest_component = metrics.largest_component.max(size);
+        }
+        metrics
+    }
+}
+
+/// Model state that the main thread needs to draw the figures.
+/// The simulation worker posts a frame after each time step, encoded with bincode.
+#[derive(Default, Serialize, Deserialize)]
+struct Frame {
+    /// Next time step to run
+    time_step: u32,
+    /// False when a stop condition holds
+    running: bool,
+    /// True when `time_series` replaces the previous results, e.g. after rewinding
+    restart: bool,
+    /// Simulation results of the time steps since the previous frame
+    time_series: std::vec::Vec<TimeStepResults>,
+    #[cfg(feature = "net-graphics")]
+    histogram_degrees_set: BTreeSet<i32>,
+    #[cfg(feature = "net-graphics")]
+    histogram_max_degree: i32,
+    #[cfg(feature = "net-graphics")]
+    histogram_height: u32,
+    #[cfg(feature = "graphics")]
+    agent_time_series_height: u32,
+    #[cfg(feature = "landscape-graphics")]
+    cell_time_series_height: u32,
+    /// Health of the agents in the network graph
+    #[cfg(feature = "net-graphics")]
+    graph_health: SecondaryMap<AgentKey, Health>,
+    /// Links between agents in the network graph
+    #[cfg(feature = "net-graphics")]
+    graph_links: std::vec::Vec<(AgentKey, AgentKey)>,
+    #[cfg(feature = "net-graphics")]
+    graph_layout: SecondaryMap<AgentKey, (f64, f64)>,
+    /// Cells whose health changed since the previous frame
+    #[cfg(feature = "landscape")]
+    cell_changes: std::vec::Vec<(u32, Health)>,
+    /// Cells whose health changed in each result of `time_series`; the results carry no `cell_health`.
+    /// None for the results that no longer keep a copy of the landscape.
+    #[cfg(feature = "landscape-graphics")]
+    cell_series_changes: std::vec::Vec<Option<std::vec::Vec<(u32, Health)>>>,
+    #[cfg(feature = "landscape")]
+    cell_visits: std::vec::Vec<(u32, u32)>,
+    /// Dimensions of the landscape, so that the main thread can mirror comparison runs; 0 without the landscape
+    world_length: i32,
+    /// Frames of the comparison runs
+    comparisons: std::vec::Vec<Frame>,
+    /// Replicate results of the time steps since the previous frame
+    bands: std::vec::Vec<Band>,
+    /// Infections of agents since the previous frame
+    #[cfg(feature = "infection-events")]
+    infection_events: std::vec::Vec<InfectionEvent>,
+}
+
+/// Model state at the start of a time step, to rewind the simulation
+#[derive(Clone, Serialize, Deserialize)]
+struct Snapshot {
+    time_step: u32,
+    rng: rand_pcg::Pcg64,
+    health: SlotMap<AgentKey, Health>,
+    #[cfg(feature = "net")]
+    links: SlotMap<LinkKey, (AgentKey, AgentKey)>,
+    #[cfg(feature = "landscape")]
+    cell_health: std::vec::Vec<Health>,
+    #[cfg(feature = "landscape")]
+    next_cell_health: std::vec::Vec<Health>,
+    #[cfg(feature = "contact-tracing")]
+    quarantine: SecondaryMap<AgentKey, u32>,
+    #[cfg(feature = "agent-metrics")]
+    flows: (u32, u32, u32),
+    #[cfg(feature = "landscape")]
+    cell_visits: std::vec::Vec<(u32, u32)>,
+}
+
+/// Complete simulation state, for saving and loading runs
+#[derive(Serialize, Deserialize)]
+struct SavedRun {
+    #[cfg(feature = "landscape")]
+    world_length: i32,
+    scenario: Scenario,
+    state: Snapshot,
+    /// Number of time steps between snapshots, so that a loaded run rewinds like the saved one
+    snapshot_interval: u32,
+    /// Vacant slots of `state.health`, in the order that new agents fill them
+    vacant_agents: std::vec::Vec<u32>,
+    /// Vacant slots of `state.links`, in the order that new links fill them
+    #[cfg(feature = "net")]
+    vacant_links: std::vec::Vec<u32>,
+}
+
+/// Saved runs start with these bytes, followed by the format version and the `SavedRun` in bincode
+const SAVED_RUN_MAGIC: &[u8; 4] = b"WABM";
+
+/// Version of the saved run format; increase it whenever `SavedRun` changes
+const SAVED_RUN_VERSION: u32 = 4;
+
+/// Indices of the vacant slots of a slot map, in the order that it fills them.
+/// Serde does not keep this order, but the keys of new agents and links depend on it.
+fn vacant_slots<K: slotmap::Key, V: Clone>(map: &SlotMap<K, V>, filler: V) -> std::vec::Vec<u32> {
+    let mut map = map.clone();
+    let mut vacant = std::vec::Vec::new();
+    loop {
+        let key = slotmap::Key::data(&map.insert(filler.clone())).as_ffi();
+        // A new slot starts at version 1; reused slots have higher versions
+        if key >> 32 == 1 {
+            return vacant;
+        }
+        vacant.push(key as u32);
+    }
+}
+
+/// A slot in the serde format of slotmap, which Cargo.toml pins to an exact version
+#[derive(Serialize, Deserialize)]
+struct Slot<V> {
+    value: Option<V>,
+    version: u32,
+}
+
+/// Makes a deserialized slot map fill its vacant slots in the order that `vacant_slots` found
+fn refill_in_order<K, V>(map: SlotMap<K, V>, vacant: &[u32], filler: V) -> Option<SlotMap<K, V>>
+where
+    K: slotmap::Key,
+    V: Clone + Serialize + for<'de> Deserialize<'de>,
+{
+    let mut slots: std::vec::Vec<Slot<V>> =
+        bincode::deserialize(&bincode::serialize(&map).ok()?).ok()?;
+    // Filling and then emptying a slot increases its version by 2
+    for &idx in vacant {
+        let slot = slots
+            .get_mut(idx as usize)
+            .filter(|slot| slot.value.is_none())?;
+        slot.version = slot.version.checked_sub(2)?;
+    }
+    let mut map: SlotMap<K, V> = bincode::deserialize(&bincode::serialize(&slots).ok()?).ok()?;
+    let mut keys: std::vec::Vec<K> = vacant.iter().map(|_| map.insert(filler.clone())).collect();
+    keys.sort_by_key(|key| key.data().as_ffi() as u32);
+    // The slot emptied last is the first to be filled
+    for &idx in vacant.iter().rev() {
+        let position = keys
+            .binary_search_by_key(&idx, |key| key.data().as_ffi() as u32)
+            .ok()?;
+        map.remove(keys[position])?;
+    }
+    Some(map)
+}
+
+/// Maximum number of snapshots per run; beyond this, snapshots become sparser
+const MAX_SNAPSHOTS: usize = 100;
+
+/// Number of recent results that keep a copy of the landscape; older results only keep their measurements
+#[cfg(feature = "landscape-graphics")]
+const MAX_LANDSCAPE_HISTORY: usize = 1000;
+
+/// Drops the copy of the landscape from the result that has just left the recent history
+#[cfg(feature = "landscape-graphics")]
+fn forget_old_landscape(time_series: &mut [TimeStepResults]) {
+    if let Some(idx) = time_series.len().checked_sub(MAX_LANDSCAPE_HISTORY + 1) {
+        time_series[idx].cell_health = std::vec::Vec::new();
+    }
+}
+
+/// Cells whose health differs from the previous landscape, or from a susceptible landscape when there is none
+#[cfg(feature = "landscape")]
+fn cell_changes(
+    previous: Option<&[Health]>,
+    cell_health: &[Health],
+) -> std::vec::Vec<(u32, Health)> {
+    cell_health
+        .iter()
+        .enumerate()
+        .filter(|&(idx, &h)| {
+            previous
+                .and_then(|previous| previous.get(idx))
+                .map_or(h != Health::S, |&p| p != h)
+        })
+        .map(|(idx, &h)| (idx as u32, h))
+        .collect()
+}
+
+/// Applies the changes that `cell_changes` found
+#[cfg(feature = "landscape")]
+fn apply_cell_changes(cell_health: &mut [Health], changes: &[(u32, Health)]) {
+    for &(idx, h) in changes {
+        if let Some(cell) = cell_health.get_mut(idx as usize) {
+            *cell = h;
+        }
+    }
+}
+
+impl Frame {
+    fn encode(&self) -> Result<std::vec::Vec<u8>, AbmError> {
+        bincode::serialize(self).map_err(|e| AbmError::Internal(format!("encoding a frame: {}", e)))
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, AbmError> {
+        bincode::deserialize(bytes)
+            .map_err(|e| AbmError::Internal(format!("decoding a frame: {}", e)))
+    }
+}
+
+/// Median and 5-95% band of the replicate runs in a time step, as [5%, median, 95%]
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Band {
+    time_step: u32,
+    /// Number of replicate runs
+    replicates: u32,
+    /// Number of agents
+    #[cfg(feature = "agent-metrics")]
+    n: [f64; 3],
+    /// Infected
+    #[cfg(feature = "agent-metrics")]
+    i: [f64; 3],
+    /// Prevalence `i / n`
+    #[cfg(feature = "agent-metrics")]
+    prevalence: [f64; 3],
+    /// Infected cells
+    #[cfg(feature = "landscape-metrics")]
+    c_i: [f64; 3],
+}
+
+impl Band {
+    /// Encodes the bands that the simulation worker posts between frames
+    fn encode(bands: &[Band]) -> Result<std::vec::Vec<u8>, AbmError> {
+        bincode::serialize(bands).map_err(|e| AbmError::Internal(format!("encoding bands: {}", e)))
+    }
+
+    fn decode(bytes: &[u8]) -> Result<std::vec::Vec<Band>, AbmError> {
+        bincode::deserialize(bytes)
+            .map_err(|e| AbmError::Internal(format!("decoding bands: {}", e)))
+    }
+
+    /// Summarizes the results of the replicate runs in the same time step
+    fn new(time_step: u32, results: &[&TimeStepResults]) -> Self {
+        Band {
+            time_step,
+            replicates: results.len() as u32,
+            #[cfg(feature = "agent-metrics")]
+            n: quantiles(results.iter().map(|tsr| tsr.n as f64)),
+            #[cfg(feature = "agent-metrics")]
+            i: quantiles(results.iter().map(|tsr| tsr.i as f64)),
+            #[cfg(feature = "agent-metrics")]
+            prevalence: quantiles(
+                results
+                    .iter()
+                    .map(|tsr| tsr.i as f64 / f64::max(tsr.n as f64, 1.0)),
+            ),
+            #[cfg(feature = "landscape-metrics")]
+            c_i: quantiles(results.iter().map(|tsr| tsr.c_i as f64)),
+        }
+    }
+}
+
+/// 5% quantile, median, and 95% quantile of at least one value, by the nearest rank
+#[cfg(any(feature = "agent-metrics", feature = "landscape-metrics"))]
+fn quantiles(values: impl Iterator<Item = f64>) -> [f64; 3] {
+    let mut values: std::vec::Vec<f64> = values.collect();
+    values.sort_by(f64::total_cmp);
+    let at = |q: f64| values[((values.len() - 1) as f64 * q).round() as usize];
+    [at(0.05), at(0.5), at(0.95)]
+}
+
+/// Maximum number of replicate runs
+const MAX_REPLICATES: u32 = 100;
+
 /// Simulation scenario, including parameters and results
-#[derive(Clone, Default)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 struct Scenario {
     /// Sequential scenario number
     id: u32,
     /// Model parameter: Infection probability
     infection_probability: f64,
+    /// Model parameter: Demographic regime
+    demography: Demography,
+    /// Model parameter: Birth probability per agent and time step
+    birth_rate: f64,
+    /// Model parameter: Number of immigrants per time step
+    immigration: u32,
+    /// Model parameter: Carrying capacity for logistic growth
+    carrying_capacity: u32,
+    /// Model parameter: Source of information about the risk of infection
+    #[cfg(feature = "behavior")]
+    risk_perception: RiskPerception,
+    /// Model parameter: Sensitivity of agents to the perceived prevalence
+    #[cfg(feature = "behavior")]
+    awareness: f64,
+    /// Model parameter: Probability that an aware agent actually self-isolates
+    #[cfg(feature = "behavior")]
+    compliance: f64,
+    /// Model parameter: Probability that testing detects an infected agent
+    #[cfg(feature = "contact-tracing")]
+    detection_probability: f64,
+    /// Model parameter: Maximum number of detected agents whose contacts are traced each time step
+    #[cfg(feature = "contact-tracing")]
+    tracing_capacity: u32,
+    /// Model parameter: Duration of quarantine, in time steps; 0 counts as 1
+    #[cfg(feature = "contact-tracing")]
+    quarantine_duration: u32,
+    /// Stop condition: Maximum number of time steps; 0 means no limit
+    max_steps: u32,
+    /// Stop condition: Stop when no agents or cells are infected
+    stop_on_disease_extinction: bool,
+    /// Stop condition: Stop when no agents are alive
+    stop_on_population_extinction: bool,
+    /// Stop condition: Stop when the prevalence `i / n` reaches this value; 0 means no threshold
+    prevalence_threshold: f64,
+    /// Figure parameter: Panels of the dashboard and their sizes
+    #[cfg(feature = "graphics")]
+    dashboard: Dashboard,
+    /// Figure parameter: Colour palette of the figures
+    #[cfg(feature = "graphics")]
+    palette: Palette,
+    /// Figure parameter: Colours of susceptible and infected agents in the custom palette
+    #[cfg(feature = "graphics")]
+    custom_colors: [(u8, u8, u8); 2],
+    /// Figure parameter: Series in the chart of agent populations
+    #[cfg(feature = "graphics")]
+    agent_series: std::vec::Vec<Series>,
+    /// Figure parameter: Show agent populations as proportions of `n`
+    #[cfg(feature = "graphics")]
+    proportional: bool,
+    /// Figure parameter: Maximum number of agents drawn in the network graph
+    #[cfg(feature = "net-graphics")]
+    graph_nodes: u32,
+    /// Figure parameter: Presentation of the network degree histogram
+    #[cfg(feature = "net-graphics")]
+    histogram_mode: HistogramMode,
+    /// Measurement parameter: Time steps between measurements of the network structure; 0 means never
+    #[cfg(feature = "net-metrics")]
+    net_metrics_interval: u32,
+    /// Figure parameter: Series in the chart of network metrics
+    #[cfg(feature = "net-graphics")]
+    net_series: std::vec::Vec<NetSeries>,
     /// Simulation results: Set of network degrees that ever ocurred in this scenario
     #[cfg(feature = "net-graphics")]
     histogram_degrees_set: BTreeSet<i32>,
@@ -124,162 +896,1458 @@ struct Scenario {
     /// Simulation results: Height of the time series figure for agents for this scenario
     #[cfg(feature = "landscape-graphics")]
     cell_time_series_height: u32,
-    /// Simulation results for all time steps
+    /// Simulation results for all time steps, one per time step; the time series figures and the summary read them.
+    /// Only the latest results keep a copy of the landscape, so that long runs do not exhaust memory.
     time_series: std::vec::Vec<TimeStepResults>,
+    /// Simulation results: Infections of agents, in the order they happened
+    #[cfg(feature = "infection-events")]
+    infection_events: std::vec::Vec<InfectionEvent>,
 }
 
 // end-similar-code 0
 
-#[wasm_bindgen]
-pub fn rs_deploy_scenario() {
-    // This provides better error messages in debug mode.
-    // It's disabled in release mode so it doesn't bloat up the file size.
-    #[cfg(debug_assertions)]
-    console_error_panic_hook::set_once();
-
-    // Your code goes here!
-    // let window = web_sys::window().expect("Trying to communicate with the web browser window");
-    // let document = window
-    //     .document()
-    //     .expect("Trying to communicate with the HTML document");
-
-    // js_message(&format!(
-    //     "Welcome to wasm-agent-based-models! n0 = {}",
-    //     js_n0()
-    // ));
-
-    let world_length = js_world_length();
-    // begin-similar-code 1
-    // Model parameter: Initial number of agents
-    let n0: usize = js_n0() as usize;
-    // Model parameter: Scale-free network parameter: new links per agent
-    #[cfg(feature = "net")]
-    let net_k: usize = 7;
-    // Model parameter: Dimensions of the virtual landscape, in number of cells
-    #[cfg(feature = "landscape")]
-    let coord = WrappingCoords2d::new(world_length, world_length).unwrap();
-    let birth_distro = Bernoulli::new(0.01).unwrap();
-    let initial_infection_distro = Bernoulli::new(0.3).unwrap();
-    // Normal distribution to choose cells in the landscape
-    #[cfg(feature = "landscape")]
-    let visit_distro = Normal::new(50.0f32, 10f32).unwrap();
-    #[cfg(feature = "net")]
-    let link_distro = Bernoulli::new(0.01).unwrap();
-    let recovery_distro = Bernoulli::new(0.8).unwrap();
-    let survival_distro = Bernoulli::new(0.8).unwrap();
-    // end-similar-code 1
-    #[cfg(feature = "net-graphics")]
-    let compress_histogram = false;
-    let mut scenario = Scenario::default();
-
-    // begin-similar-code 2
-    // Use Pcg64 for reproducible random numbers; change to thread_rng for production
-    // let mut rng = rand::thread_rng();
-    #[allow(clippy::unreadable_literal)]
-    let mut rng = rand_pcg::Pcg64::new(0xcafef00dd15ea5e5, 0xa02bdbf7bb3c0a7ac28fa16a64abf96);
-    // Model state: Agent health
-    let mut health = SlotMap::with_capacity_and_key(2 * n0);
-    // Model state: Bidirectional links between agents
-    #[cfg(feature = "net")]
-    let mut links = slotmap::SlotMap::with_capacity_and_key(n0 * n0);
-    // Model state: Health status of each cell in the landscape
-    #[cfg(feature = "landscape")]
-    let mut cell_health = vec![Health::S; coord.size()];
-    // Model state: Cell health storage for the next time step. This implements parallel updating of cells.
-    #[cfg(feature = "landscape")]
-    let mut next_cell_health = cell_health.clone();
-    // Model initialization: Agents
-    while health.len() < n0 {
-        let _k: AgentKey = health.insert(Health::S);
-    }
-    // end-similar-code 2
-    let mut time_step = 0;
-    let rs_step_closure = Closure::wrap(Box::new(move || {
-        let mut time_step_results: TimeStepResults = Default::default();
-        scenario.infection_probability = js_infection_probability();
-        let infection_distro = Bernoulli::new(scenario.infection_probability).unwrap();
+impl Scenario {
+    /// Name of the run in the figures; run 1 follows the page parameters
+    fn label(&self) -> String {
+        format!("Run {}", self.id + 1)
+    }
 
-        // begin-similar-code 3
-        // Initialization of this time step: Network seed
-        #[cfg(feature = "net")]
+    /// Takes the model parameters that drive the dynamics; the stop conditions stay as they are
+    fn copy_dynamics(&mut self, other: &Self) {
+        self.infection_probability = other.infection_probability;
+        self.demography = other.demography;
+        self.birth_rate = other.birth_rate;
+        self.immigration = other.immigration;
+        self.carrying_capacity = other.carrying_capacity;
+        #[cfg(feature = "behavior")]
         {
-            if links.is_empty() && health.len() > 1 {
-                let mut h_it = health.iter();
-                let (key0, _value) = h_it.next().unwrap();
-                let (key1, _value) = h_it.next().unwrap();
-                let _link_id: LinkKey = links.insert((key0, key1));
-            }
-            // Initialization of this time step: Network
-            let keys_vec: Vec<AgentKey> = health.keys().collect();
-            let mut idx_map = SecondaryMap::with_capacity(health.capacity());
-            let mut weights_vec: Vec<i32> = {
-                let mut weights_map = SecondaryMap::with_capacity(health.capacity());
-                keys_vec.iter().enumerate().for_each(|(idx, &k)| {
-                    weights_map.insert(k, 0);
-                    idx_map.insert(k, idx);
-                });
-                links.values().for_each(|&(key0, key1)| {
-                    weights_map[key0] += 1;
-                    weights_map[key1] += 1;
-                });
-                keys_vec.iter().map(|&k| weights_map[k]).collect()
-            };
-            keys_vec
-                .iter()
-                .enumerate()
-                .for_each(|(agent_idx, &agent_key)| {
-                    let new_links = if weights_vec[agent_idx] == 0 {
-                        net_k
-                    } else if link_distro.sample(&mut rng) {
-                        1
-                    } else {
-                        0
-                    };
-                    if new_links > 0 {
-                        let mut weights_tmp = weights_vec.clone();
-                        // This agent cannot make a link to itself; set its weight to 0.
-                        weights_tmp[agent_idx] = 0;
-                        // Friends are ineligible for a new link; set friends' weights to 0.
-                        links.values().for_each(|&(key0, key1)| {
-                            if key0 == agent_key {
-                                weights_tmp[idx_map[key1]] = 0;
-                            }
-                            if key1 == agent_key {
-                                weights_tmp[idx_map[key0]] = 0;
-                            }
-                        });
-                        match WeightedIndex::new(weights_tmp) {
-                            Ok(mut dist) => {
-                                let mut k = 0;
-                                loop {
-                                    let friend_idx = dist.sample(&mut rng);
-                                    links.insert((agent_key, keys_vec[friend_idx]));
-                                    weights_vec[agent_idx] += 1;
-                                    weights_vec[friend_idx] += 1;
-                                    k += 1;
-                                    if k == new_links {
-                                        break;
-                                    }
-                                    // Make friend ineligible for a new link; set its weight to 0.
-                                    if dist.update_weights(&[(friend_idx, &0)]).is_err() {
-                                        break;
-                                    }
-                                }
-                            }
-                            Err(WeightedError::AllWeightsZero) => {}
-                            Err(e) => {
-                                panic!("Internal error OsXJWc0sHx: {}. Please debug.", e)
-                            }
-                        }
-                    }
-                });
-            // Model measurements: Network
-            #[cfg(feature = "net-metrics")]
-            {
-                time_step_results.d_s = match keys_vec
-                    .iter()
-                    .zip(weights_vec.iter())
-                    .filter(|(&k, _w)| health[k] == Health::S)
+            self.risk_perception = other.risk_perception;
+            self.awareness = other.awareness;
+            self.compliance = other.compliance;
+        }
+        #[cfg(feature = "contact-tracing")]
+        {
+            self.detection_probability = other.detection_probability;
+            self.tracing_capacity = other.tracing_capacity;
+            self.quarantine_duration = other.quarantine_duration;
+        }
+    }
+}
+
+/// Presentation of the network degree histogram
+#[cfg(feature = "net-graphics")]
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+enum HistogramMode {
+    /// One bar for each degree from zero to the maximum degree
+    #[default]
+    Linear,
+    /// One bar for each degree that ever ocurred in this scenario
+    Compressed,
+    /// Complementary cumulative distribution on log-log axes
+    LogLog,
+}
+
+#[cfg(feature = "net-graphics")]
+impl HistogramMode {
+    /// Parses the value of the histogram mode selector in index.html
+    fn from_js(value: &str) -> Self {
+        match value {
+            "compressed" => HistogramMode::Compressed,
+            "log-log" => HistogramMode::LogLog,
+            _ => HistogramMode::Linear,
+        }
+    }
+}
+
+/// Maximum likelihood estimate of the exponent of a discrete power law, using the continuous approximation of Clauset, Shalizi and Newman (2009) with `k_min = 1`; agents without links are excluded
+#[cfg(feature = "net-graphics")]
+fn power_law_exponent(degree_histogram: &BTreeMap<i32, u32>) -> Option<f64> {
+    let (n, sum) = degree_histogram
+        .iter()
+        .filter(|(&degree, _)| degree > 0)
+        .fold((0.0, 0.0), |(n, sum), (&degree, &frequency)| {
+            (
+                n + frequency as f64,
+                sum + frequency as f64 * (degree as f64 / 0.5).ln(),
+            )
+        });
+    if n > 0.0 {
+        Some(1.0 + n / sum)
+    } else {
+        None
+    }
+}
+
+/// Fraction of agents with degree `k` or more, for each degree `k > 0` that occurs
+#[cfg(feature = "net-graphics")]
+fn degree_ccdf(degree_histogram: &BTreeMap<i32, u32>) -> std::vec::Vec<(i32, f64)> {
+    let n: u32 = degree_histogram
+        .iter()
+        .filter(|(&degree, _)| degree > 0)
+        .map(|(_, &frequency)| frequency)
+        .sum();
+    let mut remaining = n;
+    degree_histogram
+        .iter()
+        .filter(|(&degree, _)| degree > 0)
+        .map(|(&degree, &frequency)| {
+            let p = remaining as f64 / n as f64;
+            remaining -= frequency;
+            (degree, p)
+        })
+        .collect()
+}
+
+/// Colour palettes of the figures
+#[cfg(feature = "graphics")]
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+enum Palette {
+    /// The original colours of the model
+    #[default]
+    Classic,
+    /// Colour-blind-safe palette by Okabe and Ito (2008)
+    OkabeIto,
+    /// Colour-blind-safe palette by Paul Tol
+    TolBright,
+    /// Shades of grey, for printing
+    Grayscale,
+    /// User-defined colours for susceptible and infected agents, with Okabe-Ito accents
+    Custom,
+}
+
+#[cfg(feature = "graphics")]
+impl Palette {
+    /// Parses the value of the palette selector in index.html
+    fn from_js(value: &str) -> Self {
+        match value {
+            "okabe-ito" => Palette::OkabeIto,
+            "tol-bright" => Palette::TolBright,
+            "grayscale" => Palette::Grayscale,
+            "custom" => Palette::Custom,
+            _ => Palette::Classic,
+        }
+    }
+}
+
+/// Parses a colour in the `#rrggbb` format of HTML colour inputs
+#[cfg(feature = "graphics")]
+fn parse_color(name: &str, value: &str) -> Result<(u8, u8, u8), AbmError> {
+    let channel = |range: core::ops::Range<usize>| {
+        value
+            .strip_prefix('#')
+            .filter(|hex| hex.len() == 6)
+            .and_then(|hex| hex.get(range))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| AbmError::Parameter(format!("{} = {} is not a colour", name, value)))
+    };
+    Ok((channel(0..2)?, channel(2..4)?, channel(4..6)?))
+}
+
+/// Colours used consistently by all figures, including exports
+#[cfg(feature = "graphics")]
+struct Theme {
+    background: RGBColor,
+    foreground: RGBColor,
+    susceptible: RGBColor,
+    infected: RGBColor,
+    /// Self-isolating agents and other highlights
+    accent: RGBColor,
+    prevalence: RGBColor,
+    incidence: RGBColor,
+    births: RGBColor,
+}
+
+#[cfg(feature = "graphics")]
+impl Theme {
+    fn new(scenario: &Scenario, dark_figures: bool) -> Self {
+        let rgb = |(r, g, b): (u8, u8, u8)| RGBColor(r, g, b);
+        let (background, foreground) = if dark_figures {
+            (BLACK, WHITE)
+        } else {
+            (WHITE, BLACK)
+        };
+        let okabe_ito = Theme {
+            background,
+            foreground,
+            susceptible: RGBColor(0, 158, 115),
+            infected: RGBColor(213, 94, 0),
+            accent: RGBColor(230, 159, 0),
+            prevalence: RGBColor(0, 114, 178),
+            incidence: RGBColor(204, 121, 167),
+            births: RGBColor(86, 180, 233),
+        };
+        match scenario.palette {
+            Palette::Classic => Theme {
+                background,
+                foreground,
+                susceptible: RGBColor(0, 176, 80),
+                infected: RGBColor(32, 56, 100),
+                accent: if dark_figures {
+                    RGBColor(255, 192, 0)
+                } else {
+                    RED
+                },
+                prevalence: RGBColor(0, 112, 192),
+                incidence: RGBColor(112, 48, 160),
+                births: RGBColor(237, 125, 49),
+            },
+            Palette::OkabeIto => okabe_ito,
+            Palette::TolBright => Theme {
+                background,
+                foreground,
+                susceptible: RGBColor(68, 119, 170),
+                infected: RGBColor(238, 102, 119),
+                accent: RGBColor(204, 187, 68),
+                prevalence: RGBColor(170, 51, 119),
+                incidence: RGBColor(34, 136, 51),
+                births: RGBColor(102, 204, 238),
+            },
+            Palette::Grayscale => {
+                // Infected agents contrast the most with the background
+                let shade = |light: u8| {
+                    let level = if dark_figures { 255 - light } else { light };
+                    RGBColor(level, level, level)
+                };
+                Theme {
+                    background,
+                    foreground,
+                    susceptible: shade(190),
+                    infected: shade(40),
+                    accent: shade(120),
+                    prevalence: shade(80),
+                    incidence: shade(150),
+                    births: shade(100),
+                }
+            }
+            Palette::Custom => Theme {
+                susceptible: rgb(scenario.custom_colors[0]),
+                infected: rgb(scenario.custom_colors[1]),
+                ..okabe_ito
+            },
+        }
+    }
+}
+
+/// Series in the chart of agent populations
+#[cfg(feature = "graphics")]
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum Series {
+    N,
+    S,
+    I,
+    #[cfg(feature = "behavior")]
+    A,
+    Prevalence,
+    Incidence,
+    Deaths,
+    Births,
+}
+
+#[cfg(feature = "graphics")]
+impl Series {
+    /// Parses the comma-separated values of the series selector in index.html
+    fn from_js(values: &str) -> std::vec::Vec<Self> {
+        values
+            .split(',')
+            .filter_map(|value| match value {
+                "n" => Some(Series::N),
+                "s" => Some(Series::S),
+                "i" => Some(Series::I),
+                #[cfg(feature = "behavior")]
+                "a" => Some(Series::A),
+                "prevalence" => Some(Series::Prevalence),
+                "incidence" => Some(Series::Incidence),
+                "deaths" => Some(Series::Deaths),
+                "births" => Some(Series::Births),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Series::N => "n Number of agents",
+            Series::S => "s Susceptible agents",
+            Series::I => "i Infected agents",
+            #[cfg(feature = "behavior")]
+            Series::A => "a Self-isolating agents",
+            Series::Prevalence => "i/n Prevalence",
+            Series::Incidence => "New infections per time step",
+            Series::Deaths => "Deaths per time step",
+            Series::Births => "Births per time step",
+        }
+    }
+
+    /// Value of the series in absolute terms; prevalence is always a proportion
+    fn value(self, tsr: &TimeStepResults) -> f64 {
+        match self {
+            Series::N => tsr.n as f64,
+            Series::S => tsr.s as f64,
+            Series::I => tsr.i as f64,
+            #[cfg(feature = "behavior")]
+            Series::A => tsr.a as f64,
+            Series::Prevalence => tsr.i as f64 / f64::max(tsr.n as f64, 1.0),
+            Series::Incidence => tsr.incidence as f64,
+            Series::Deaths => tsr.deaths as f64,
+            Series::Births => tsr.births as f64,
+        }
+    }
+}
+
+/// Network metric in the chart of network metrics
+#[cfg(feature = "net-graphics")]
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum NetSeries {
+    MeanDegree,
+    MeanDegreeS,
+    MeanDegreeI,
+    Clustering,
+    Assortativity,
+    Components,
+    LargestComponent,
+}
+
+#[cfg(feature = "net-graphics")]
+impl NetSeries {
+    /// Parses the comma-separated values of the network series selector in index.html
+    fn from_js(values: &str) -> std::vec::Vec<Self> {
+        values
+            .split(',')
+            .filter_map(|value| match value {
+                "mean_degree" => Some(NetSeries::MeanDegree),
+                "mean_degree_s" => Some(NetSeries::MeanDegreeS),
+                "mean_degree_i" => Some(NetSeries::MeanDegreeI),
+                "clustering" => Some(NetSeries::Clustering),
+                "assortativity" => Some(NetSeries::Assortativity),
+                "components" => Some(NetSeries::Components),
+                "largest_component" => Some(NetSeries::LargestComponent),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            NetSeries::MeanDegree => "Mean degree",
+            NetSeries::MeanDegreeS => "Mean degree of susceptibles",
+            NetSeries::MeanDegreeI => "Mean degree of infected agents",
+            NetSeries::Clustering => "Clustering coefficient",
+            NetSeries::Assortativity => "Degree assortativity",
+            NetSeries::Components => "Connected components",
+            NetSeries::LargestComponent => "Agents in the largest component",
+        }
+    }
+
+    fn value(self, metrics: &NetMetrics) -> f64 {
+        match self {
+            NetSeries::MeanDegree => metrics.mean_degree,
+            NetSeries::MeanDegreeS => metrics.mean_degree_s,
+            NetSeries::MeanDegreeI => metrics.mean_degree_i,
+            NetSeries::Clustering => metrics.clustering,
+            NetSeries::Assortativity => metrics.assortativity,
+            NetSeries::Components => metrics.components as f64,
+            NetSeries::LargestComponent => metrics.largest_component as f64,
+        }
+    }
+}
+
+/// Panels of the dashboard
+#[cfg(feature = "graphics")]
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum Panel {
+    /// Parameters and summary statistics
+    Header,
+    /// Histogram of network degrees
+    #[cfg(feature = "net-graphics")]
+    Degrees,
+    /// Time series of agent populations
+    Agents,
+    /// Time series of infected cells
+    #[cfg(feature = "landscape")]
+    Cells,
+    /// Map of the landscape
+    #[cfg(feature = "landscape")]
+    Landscape,
+    /// Force-directed drawing of the network
+    #[cfg(feature = "net-graphics")]
+    Network,
+    /// Time series of network metrics, one chart per metric
+    #[cfg(feature = "net-graphics")]
+    Metrics,
+    /// Heatmap of the latest parameter sweep
+    Sweep,
+    /// Transmission tree of the earliest infections
+    #[cfg(feature = "infection-events")]
+    Tree,
+    /// Distribution of secondary cases per infection
+    #[cfg(feature = "infection-events")]
+    Secondary,
+}
+
+#[cfg(feature = "graphics")]
+impl Panel {
+    /// Parses a panel name in the dashboard layout
+    fn from_name(name: &str) -> Result<Self, AbmError> {
+        match name {
+            "header" => Ok(Panel::Header),
+            #[cfg(feature = "net-graphics")]
+            "degrees" => Ok(Panel::Degrees),
+            "agents" => Ok(Panel::Agents),
+            #[cfg(feature = "landscape")]
+            "cells" => Ok(Panel::Cells),
+            #[cfg(feature = "landscape")]
+            "landscape" => Ok(Panel::Landscape),
+            #[cfg(feature = "net-graphics")]
+            "network" => Ok(Panel::Network),
+            #[cfg(feature = "net-graphics")]
+            "metrics" => Ok(Panel::Metrics),
+            "sweep" => Ok(Panel::Sweep),
+            #[cfg(feature = "infection-events")]
+            "tree" => Ok(Panel::Tree),
+            #[cfg(feature = "infection-events")]
+            "secondary" => Ok(Panel::Secondary),
+            _ => Err(AbmError::Parameter(format!(
+                "the dashboard panel \"{}\" is unknown or unavailable in this build",
+                name
+            ))),
+        }
+    }
+}
+
+/// Declarative layout of the figures, such as `header, degrees, agents:2, cells | landscape`.
+/// The first column stacks the charts; the optional column after `|` is square.
+/// Each panel takes a share of its column given by its relative size, which defaults to 1.
+#[cfg(feature = "graphics")]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+struct Dashboard {
+    /// Panels in the chart column, with their relative sizes
+    charts: std::vec::Vec<(Panel, u32)>,
+    /// Panels in the square column, with their relative sizes
+    side: std::vec::Vec<(Panel, u32)>,
+}
+
+#[cfg(feature = "graphics")]
+impl Default for Dashboard {
+    fn default() -> Self {
+        Dashboard {
+            charts: vec![
+                (Panel::Header, 1),
+                #[cfg(feature = "net-graphics")]
+                (Panel::Degrees, 1),
+                (Panel::Agents, 1),
+                #[cfg(feature = "landscape")]
+                (Panel::Cells, 1),
+            ],
+            side: vec![
+                #[cfg(feature = "landscape")]
+                (Panel::Landscape, 1),
+            ],
+        }
+    }
+}
+
+#[cfg(feature = "graphics")]
+impl Dashboard {
+    /// Parses the dashboard layout; an empty layout means the default one
+    fn parse(layout: &str) -> Result<Self, AbmError> {
+        if layout.trim().is_empty() {
+            return Ok(Default::default());
+        }
+        let mut columns = layout.split('|').map(|column| {
+            column
+                .split(',')
+                .map(str::trim)
+                .filter(|entry| !entry.is_empty())
+                .map(|entry| match entry.split_once(':') {
+                    Some((name, size)) => match size.trim().parse::<u32>() {
+                        Ok(size) if size > 0 => Ok((Panel::from_name(name.trim())?, size)),
+                        _ => Err(AbmError::Parameter(format!(
+                            "the size of the dashboard panel \"{}\" must be a positive integer",
+                            entry
+                        ))),
+                    },
+                    None => Ok((Panel::from_name(entry)?, 1)),
+                })
+                .collect::<Result<std::vec::Vec<_>, AbmError>>()
+        });
+        let charts = columns.next().unwrap_or_else(|| Ok(vec![]))?;
+        let side = columns.next().unwrap_or_else(|| Ok(vec![]))?;
+        if columns.next().is_some() {
+            return Err(AbmError::Parameter(
+                "the dashboard has at most two columns separated by |".to_string(),
+            ));
+        }
+        Ok(Dashboard { charts, side })
+    }
+
+    fn contains(&self, panel: Panel) -> bool {
+        self.charts
+            .iter()
+            .chain(self.side.iter())
+            .any(|&(p, _size)| p == panel)
+    }
+
+    /// Stacks the panels of one column according to their relative sizes
+    fn split_column<DB: DrawingBackend>(
+        &self,
+        area: &DrawingArea<DB, plotters::coord::Shift>,
+        column: &[(Panel, u32)],
+    ) -> std::vec::Vec<(Panel, DrawingArea<DB, plotters::coord::Shift>)> {
+        let total: u32 = column.iter().map(|&(_panel, size)| size).sum();
+        let height = area.dim_in_pixel().1 as u64;
+        let mut accumulated = 0;
+        let breakpoints: std::vec::Vec<u32> = column
+            .iter()
+            .take(column.len().saturating_sub(1))
+            .map(|&(_panel, size)| {
+                accumulated += size;
+                (height * accumulated as u64 / total as u64) as u32
+            })
+            .collect();
+        column
+            .iter()
+            .map(|&(panel, _size)| panel)
+            .zip(area.split_by_breakpoints::<u32, u32, _, _>([], breakpoints))
+            .collect()
+    }
+}
+
+/// Pixel rectangles of the last drawing on the canvas, for pointer interaction
+#[cfg(feature = "graphics")]
+#[derive(Clone, Default)]
+struct Geometry {
+    /// Top-left corner and size of the landscape map
+    #[cfg(feature = "landscape-graphics")]
+    landscape: Option<((i32, i32), (u32, u32))>,
+    /// Coordinate transform of each time series chart
+    time_charts: std::vec::Vec<Cartesian2d<RangedCoordu32, RangedCoordf64>>,
+}
+
+#[cfg(feature = "graphics")]
+impl Geometry {
+    /// Time series chart under a canvas pixel
+    fn time_chart(&self, x: i32, y: i32) -> Option<&Cartesian2d<RangedCoordu32, RangedCoordf64>> {
+        self.time_charts.iter().find(|spec| {
+            let x_pixels = spec.get_x_axis_pixel_range();
+            let y_pixels = spec.get_y_axis_pixel_range();
+            x >= x_pixels.start.min(x_pixels.end)
+                && x <= x_pixels.start.max(x_pixels.end)
+                && y >= y_pixels.start.min(y_pixels.end)
+                && y <= y_pixels.start.max(y_pixels.end)
+        })
+    }
+}
+
+/// Errors that stop a time step without crashing the page
+#[derive(Clone, Debug)]
+enum AbmError {
+    /// A model parameter is out of range
+    Parameter(String),
+    /// The page lacks an element the model needs
+    Page(String),
+    /// Plotters failed to draw the figures
+    #[cfg(feature = "graphics")]
+    Drawing(String),
+    /// A bug in the model
+    Internal(String),
+}
+
+impl std::fmt::Display for AbmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AbmError::Parameter(msg) => write!(f, "invalid parameter: {}", msg),
+            AbmError::Page(msg) => write!(f, "the page is missing {}", msg),
+            #[cfg(feature = "graphics")]
+            AbmError::Drawing(msg) => write!(f, "cannot draw the figures: {}", msg),
+            AbmError::Internal(msg) => write!(f, "internal error {}; please debug", msg),
+        }
+    }
+}
+
+#[cfg(feature = "graphics")]
+impl<E: std::error::Error + Send + Sync> From<DrawingAreaErrorKind<E>> for AbmError {
+    fn from(e: DrawingAreaErrorKind<E>) -> Self {
+        AbmError::Drawing(e.to_string())
+    }
+}
+
+/// Bernoulli distribution for a probability that may come from the user
+fn bernoulli(name: &str, p: f64) -> Result<Bernoulli, AbmError> {
+    Bernoulli::new(p)
+        .map_err(|_| AbmError::Parameter(format!("{} = {} is not a probability", name, p)))
+}
+
+/// Model parameters that a sweep can vary; the names match the selectors in index.html
+#[cfg(feature = "graphics")]
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum SweepParameter {
+    InfectionProbability,
+    BirthRate,
+    Immigration,
+    CarryingCapacity,
+    #[cfg(feature = "behavior")]
+    Awareness,
+    #[cfg(feature = "behavior")]
+    Compliance,
+    #[cfg(feature = "contact-tracing")]
+    DetectionProbability,
+    #[cfg(feature = "contact-tracing")]
+    TracingCapacity,
+    #[cfg(feature = "contact-tracing")]
+    QuarantineDuration,
+}
+
+#[cfg(feature = "graphics")]
+impl SweepParameter {
+    /// Parses the value of a sweep parameter selector; None means no parameter
+    fn from_js(value: &str) -> Option<Self> {
+        match value {
+            "infection_probability" => Some(SweepParameter::InfectionProbability),
+            "birth_rate" => Some(SweepParameter::BirthRate),
+            "immigration" => Some(SweepParameter::Immigration),
+            "carrying_capacity" => Some(SweepParameter::CarryingCapacity),
+            #[cfg(feature = "behavior")]
+            "awareness" => Some(SweepParameter::Awareness),
+            #[cfg(feature = "behavior")]
+            "compliance" => Some(SweepParameter::Compliance),
+            #[cfg(feature = "contact-tracing")]
+            "detection_probability" => Some(SweepParameter::DetectionProbability),
+            #[cfg(feature = "contact-tracing")]
+            "tracing_capacity" => Some(SweepParameter::TracingCapacity),
+            #[cfg(feature = "contact-tracing")]
+            "quarantine_duration" => Some(SweepParameter::QuarantineDuration),
+            _ => None,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SweepParameter::InfectionProbability => "Infection probability",
+            SweepParameter::BirthRate => "Birth rate",
+            SweepParameter::Immigration => "Immigration",
+            SweepParameter::CarryingCapacity => "K Carrying capacity",
+            #[cfg(feature = "behavior")]
+            SweepParameter::Awareness => "Awareness",
+            #[cfg(feature = "behavior")]
+            SweepParameter::Compliance => "Compliance",
+            #[cfg(feature = "contact-tracing")]
+            SweepParameter::DetectionProbability => "Detection probability",
+            #[cfg(feature = "contact-tracing")]
+            SweepParameter::TracingCapacity => "Tracing capacity",
+            #[cfg(feature = "contact-tracing")]
+            SweepParameter::QuarantineDuration => "Quarantine duration",
+        }
+    }
+
+    /// Sets the parameter in a scenario; counts are rounded to the nearest integer
+    fn set(self, scenario: &mut Scenario, value: f64) {
+        let count = value.round().max(0.0) as u32;
+        match self {
+            SweepParameter::InfectionProbability => scenario.infection_probability = value,
+            SweepParameter::BirthRate => scenario.birth_rate = value,
+            SweepParameter::Immigration => scenario.immigration = count,
+            SweepParameter::CarryingCapacity => scenario.carrying_capacity = count,
+            #[cfg(feature = "behavior")]
+            SweepParameter::Awareness => scenario.awareness = value,
+            #[cfg(feature = "behavior")]
+            SweepParameter::Compliance => scenario.compliance = value,
+            #[cfg(feature = "contact-tracing")]
+            SweepParameter::DetectionProbability => scenario.detection_probability = value,
+            #[cfg(feature = "contact-tracing")]
+            SweepParameter::TracingCapacity => scenario.tracing_capacity = count,
+            #[cfg(feature = "contact-tracing")]
+            SweepParameter::QuarantineDuration => scenario.quarantine_duration = count,
+        }
+    }
+}
+
+/// Outcome of a run that a sweep summarizes
+#[cfg(feature = "graphics")]
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+enum Outcome {
+    /// Prevalence `i / n` in the last time step
+    #[default]
+    FinalPrevalence,
+    /// Maximum number of infected agents
+    PeakInfected,
+    /// First time step without infected agents or cells; runs without extinction count as the time limit
+    ExtinctionTime,
+}
+
+#[cfg(feature = "graphics")]
+impl Outcome {
+    /// Parses the value of the sweep outcome selector in index.html
+    fn from_js(value: &str) -> Self {
+        match value {
+            "peak_infected" => Outcome::PeakInfected,
+            "extinction_time" => Outcome::ExtinctionTime,
+            _ => Outcome::FinalPrevalence,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Outcome::FinalPrevalence => "Final prevalence",
+            Outcome::PeakInfected => "Peak of infected agents",
+            Outcome::ExtinctionTime => "Extinction time",
+        }
+    }
+
+    /// Outcome of a run that lasted at most `time_steps` time steps
+    fn of(self, time_series: &[TimeStepResults], time_steps: u32) -> f64 {
+        match self {
+            Outcome::FinalPrevalence => time_series
+                .last()
+                .map_or(0.0, |tsr| tsr.i as f64 / f64::max(tsr.n as f64, 1.0)),
+            Outcome::PeakInfected => time_series.iter().map(|tsr| tsr.i).max().unwrap_or(0) as f64,
+            Outcome::ExtinctionTime => time_series
+                .iter()
+                .find(|tsr| extinct(tsr))
+                .map_or(time_steps, |tsr| tsr.time_step)
+                as f64,
+        }
+    }
+}
+
+/// Colour between `low` and `high`, for `t` from 0 to 1
+#[cfg(feature = "graphics")]
+fn blend(low: &RGBColor, high: &RGBColor, t: f64) -> RGBColor {
+    let channel = |low: u8, high: u8| (low as f64 + (high as f64 - low as f64) * t).round() as u8;
+    RGBColor(
+        channel(low.0, high.0),
+        channel(low.1, high.1),
+        channel(low.2, high.2),
+    )
+}
+
+/// True when no agents or cells are infected after the first time step, when the disease emerges
+#[cfg(feature = "agent-metrics")]
+fn extinct(tsr: &TimeStepResults) -> bool {
+    #[cfg(feature = "landscape-metrics")]
+    let c_i = tsr.c_i;
+    #[cfg(not(feature = "landscape-metrics"))]
+    let c_i = 0;
+    tsr.time_step > 0 && tsr.i == 0 && c_i == 0
+}
+
+/// Model parameter: Probability that an infectious agent recovers during a time step
+const RECOVERY_PROBABILITY: f64 = 0.8;
+
+/// Model parameter: Probability that an infectious agent survives a time step
+const SURVIVAL_PROBABILITY: f64 = 0.8;
+
+/// Probability that an infectious agent neither recovers nor dies during a time step
+#[cfg(feature = "agent-metrics")]
+const INFECTIOUS_STAY: f64 = (1.0 - RECOVERY_PROBABILITY) * SURVIVAL_PROBABILITY;
+
+/// Time steps in the estimates of the reproduction number
+#[cfg(feature = "agent-metrics")]
+const REPRODUCTION_WINDOW: usize = 7;
+
+/// Epidemiological summary of a run, computed from its time series
+#[cfg(feature = "agent-metrics")]
+#[derive(Clone, Copy, Default)]
+struct Summary {
+    /// Highest number of infected agents
+    peak_infected: u32,
+    /// Proportion of infected agents at the peak
+    peak_prevalence: f64,
+    /// Time step of the peak
+    peak_time: u32,
+    /// Infections per agent at risk, i.e., the initial agents and those born or immigrated since.
+    /// Agents can be infected again after they recover, so the attack rate can exceed 1.
+    attack_rate: f64,
+    /// Time steps from the first infection to the extinction of the disease, or to the latest time step
+    duration: u32,
+    /// True when the disease went extinct
+    ended: bool,
+    /// Reproduction number during the first time steps after the initial infections
+    r0: Option<f64>,
+    /// Reproduction number during the latest time steps
+    rt: Option<f64>,
+}
+
+#[cfg(feature = "agent-metrics")]
+impl Summary {
+    /// Summary of the time series; the reproduction numbers follow the renewal equation
+    /// with a geometric generation interval, and they only count infections of agents.
+    fn of(time_series: &[TimeStepResults]) -> Option<Self> {
+        let first = time_series.first()?;
+        let last = time_series.last()?;
+        let mut summary = Summary::default();
+        let mut infections = first.i as f64;
+        let mut at_risk = first.n as f64;
+        for tsr in time_series {
+            if tsr.i > summary.peak_infected {
+                summary.peak_infected = tsr.i;
+                summary.peak_prevalence = tsr.i as f64 / tsr.n as f64;
+                summary.peak_time = tsr.time_step;
+            }
+            infections += tsr.incidence as f64;
+            at_risk += tsr.births as f64;
+        }
+        summary.attack_rate = infections / f64::max(at_risk, 1.0);
+        if let Some(onset) = time_series
+            .iter()
+            .position(|tsr| tsr.i > 0 || tsr.incidence > 0)
+        {
+            let end = time_series[onset..].iter().find(|tsr| extinct(tsr));
+            summary.ended = end.is_some();
+            summary.duration = end.unwrap_or(last).time_step - time_series[onset].time_step;
+        }
+        // Infectiousness of each time step: infections in earlier time steps, weighted by the generation interval
+        let mut infectiousness = std::vec::Vec::with_capacity(time_series.len());
+        let mut lambda = 0.0;
+        let mut previous = first.i as f64;
+        for tsr in time_series {
+            infectiousness.push((tsr.incidence as f64, lambda));
+            lambda = INFECTIOUS_STAY * lambda + (1.0 - INFECTIOUS_STAY) * previous;
+            previous = tsr.incidence as f64;
+        }
+        let reproduction = |window: &[(f64, f64)]| {
+            let (cases, expected) = window
+                .iter()
+                .fold((0.0, 0.0), |(c, e), &(x, l)| (c + x, e + l));
+            if expected > 0.0 {
+                Some(cases / expected)
+            } else {
+                None
+            }
+        };
+        // The initial infections have no infectious history
+        if let Some(start) = infectiousness.iter().position(|&(_x, l)| l > 0.0) {
+            let early = &infectiousness[start..];
+            summary.r0 = reproduction(&early[..early.len().min(REPRODUCTION_WINDOW)]);
+            summary.rt = reproduction(&early[early.len().saturating_sub(REPRODUCTION_WINDOW)..]);
+        }
+        Some(summary)
+    }
+
+    /// Two lines of text for the header panel and the messages
+    fn lines(&self) -> [String; 2] {
+        let estimate = |r: Option<f64>| r.map_or("n/a".to_string(), |r| format!("{:.2}", r));
+        [
+            format!(
+                "Peak {} infected (prevalence {:.3}) at time {}   Attack rate {:.3}   Duration {}{}",
+                self.peak_infected,
+                self.peak_prevalence,
+                self.peak_time,
+                self.attack_rate,
+                self.duration,
+                if self.ended { "" } else { "+" }
+            ),
+            format!(
+                "R0 {}   Rt {} (agents only, {} time steps)",
+                estimate(self.r0),
+                estimate(self.rt),
+                REPRODUCTION_WINDOW
+            ),
+        ]
+    }
+}
+
+/// One axis of a sweep: `steps` evenly spaced values of a model parameter from `min` to `max`
+#[cfg(feature = "graphics")]
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct SweepAxis {
+    parameter: SweepParameter,
+    min: f64,
+    max: f64,
+    steps: u32,
+}
+
+#[cfg(feature = "graphics")]
+impl SweepAxis {
+    fn new(parameter: SweepParameter, min: f64, max: f64, steps: u32) -> Result<Self, AbmError> {
+        if min.partial_cmp(&max) != Some(std::cmp::Ordering::Less) || steps < 2 {
+            return Err(AbmError::Parameter(format!(
+                "the sweep of {} needs a minimum below its maximum and at least 2 steps",
+                parameter.label()
+            )));
+        }
+        Ok(SweepAxis {
+            parameter,
+            min,
+            max,
+            steps,
+        })
+    }
+
+    fn value(&self, idx: u32) -> f64 {
+        self.min + (self.max - self.min) * idx as f64 / (self.steps - 1) as f64
+    }
+
+    /// Range of the chart axis, with room for half a cell at each end
+    fn range(&self) -> std::ops::Range<f64> {
+        let half_step = (self.max - self.min) / (self.steps - 1) as f64 / 2.0;
+        self.min - half_step..self.max + half_step
+    }
+}
+
+/// Parameter sweep: mean outcome of headless runs over a grid of one or two model parameters.
+/// The other parameters are those in the page when the sweep starts.
+#[cfg(feature = "graphics")]
+#[derive(Clone, Serialize, Deserialize)]
+struct Sweep {
+    x: SweepAxis,
+    y: Option<SweepAxis>,
+    outcome: Outcome,
+    /// Runs with different random seeds in each grid point
+    runs: u32,
+    /// Maximum duration of each run; runs also stop when the disease goes extinct
+    time_steps: u32,
+    /// Mean outcome in each grid point, row by row; None until the grid point runs, or when its parameters are invalid
+    values: std::vec::Vec<Option<f64>>,
+    /// Index of the next grid point to run
+    next: u32,
+}
+
+#[cfg(feature = "graphics")]
+impl Sweep {
+    /// Reads the sweep settings from the page
+    fn read() -> Result<Self, AbmError> {
+        let x = SweepParameter::from_js(&js_sweep_x()).ok_or_else(|| {
+            AbmError::Parameter("the sweep needs a parameter on the horizontal axis".to_string())
+        })?;
+        let x = SweepAxis::new(x, js_sweep_x_min(), js_sweep_x_max(), js_sweep_x_steps())?;
+        let y = match SweepParameter::from_js(&js_sweep_y()) {
+            Some(y) => Some(SweepAxis::new(
+                y,
+                js_sweep_y_min(),
+                js_sweep_y_max(),
+                js_sweep_y_steps(),
+            )?),
+            None => None,
+        };
+        let runs = js_sweep_runs();
+        if runs == 0 {
+            return Err(AbmError::Parameter(
+                "the sweep needs at least one run in each grid point".to_string(),
+            ));
+        }
+        let rows = y.map_or(1, |y| y.steps);
+        Ok(Sweep {
+            x,
+            y,
+            outcome: Outcome::from_js(&js_sweep_outcome()),
+            runs,
+            time_steps: js_sweep_time_steps(),
+            values: vec![None; (x.steps * rows) as usize],
+            next: 0,
+        })
+    }
+
+    fn encode(&self) -> Result<std::vec::Vec<u8>, AbmError> {
+        bincode::serialize(self).map_err(|e| AbmError::Internal(format!("encoding a sweep: {}", e)))
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, AbmError> {
+        bincode::deserialize(bytes)
+            .map_err(|e| AbmError::Internal(format!("decoding a sweep: {}", e)))
+    }
+
+    /// Runs the next grid point, with the size and dynamics of `base`, and posts the sweep.
+    /// A grid point with invalid parameters is reported and stays empty. Returns false when every grid point has run.
+    fn run_point(&mut self, base: &Model) -> Result<bool, AbmError> {
+        let idx = self.next;
+        let points = self.values.len() as u32;
+        if idx >= points {
+            return Ok(false);
+        }
+        self.next += 1;
+        match self.mean_outcome(idx, base) {
+            Ok(value) => self.values[idx as usize] = Some(value),
+            Err(e) => {
+                let mut point = format!(
+                    "{} = {}",
+                    self.x.parameter.label(),
+                    self.x.value(idx % self.x.steps)
+                );
+                if let Some(y) = &self.y {
+                    point += &format!(
+                        ", {} = {}",
+                        y.parameter.label(),
+                        y.value(idx / self.x.steps)
+                    );
+                }
+                js_error(&format!("Error: the sweep skipped {}: {}.", point, e));
+            }
+        }
+        js_sweep(self.encode()?);
+        // Progress in steps of 10%
+        if (idx + 1) * 10 / points > idx * 10 / points && idx + 1 < points {
+            js_message(&format!(
+                "The sweep finished {} of {} grid points.",
+                idx + 1,
+                points
+            ));
+        }
+        Ok(idx + 1 < points)
+    }
+
+    /// Mean outcome of the runs in grid point `idx`
+    fn mean_outcome(&self, idx: u32, base: &Model) -> Result<f64, AbmError> {
+        let mut total = 0.0;
+        for seed in 1..=self.runs as u64 {
+            let mut run = base.headless(seed)?;
+            self.x
+                .parameter
+                .set(&mut run.scenario, self.x.value(idx % self.x.steps));
+            if let Some(y) = &self.y {
+                y.parameter
+                    .set(&mut run.scenario, y.value(idx / self.x.steps));
+            }
+            for _ in 0..self.time_steps {
+                run.step()?;
+                if run.scenario.time_series.last().map_or(false, extinct) {
+                    break;
+                }
+            }
+            total += self.outcome.of(&run.scenario.time_series, self.time_steps);
+        }
+        Ok(total / self.runs as f64)
+    }
+}
+
+/// Model state and parameters that persist between time steps
+struct Model {
+    scenario: Scenario,
+    time_step: u32,
+    /// Model parameter: Scale-free network parameter: new links per agent
+    #[cfg(feature = "net")]
+    net_k: usize,
+    /// Model parameter: Dimensions of the virtual landscape, in number of cells
+    #[cfg(feature = "landscape")]
+    coord: WrappingCoords2d,
+    initial_infection_distro: Bernoulli,
+    /// Normal distribution to choose cells in the landscape
+    #[cfg(feature = "landscape")]
+    visit_distro: Normal<f32>,
+    #[cfg(feature = "net")]
+    link_distro: Bernoulli,
+    recovery_distro: Bernoulli,
+    survival_distro: Bernoulli,
+    rng: rand_pcg::Pcg64,
+    /// Model state: Agent health
+    health: SlotMap<AgentKey, Health>,
+    /// Model state: Bidirectional links between agents
+    #[cfg(feature = "net")]
+    links: SlotMap<LinkKey, (AgentKey, AgentKey)>,
+    /// Model state: Health status of each cell in the landscape
+    #[cfg(feature = "landscape")]
+    cell_health: std::vec::Vec<Health>,
+    /// Model state: Cell health storage for the next time step
+    #[cfg(feature = "landscape")]
+    next_cell_health: std::vec::Vec<Health>,
+    /// Model state: Remaining time steps of quarantine of each agent
+    #[cfg(feature = "contact-tracing")]
+    quarantine: SecondaryMap<AgentKey, u32>,
+    /// Model state: New infections, deaths, and births during the last time step
+    #[cfg(feature = "agent-metrics")]
+    flows: (u32, u32, u32),
+    /// Model state: Susceptible and infected agents that visited each cell in the last time step
+    #[cfg(feature = "landscape")]
+    cell_visits: std::vec::Vec<(u32, u32)>,
+    /// Figure state: Panels of the last drawing on the canvas
+    #[cfg(feature = "graphics")]
+    geometry: Geometry,
+    /// Figure state: First and last time steps shown in the time series charts; None shows the whole run
+    #[cfg(feature = "graphics")]
+    time_window: Option<(u32, u32)>,
+    /// Figure state: Position of the sampled agents in the network graph, in the unit square
+    #[cfg(feature = "net-graphics")]
+    graph_layout: SecondaryMap<AgentKey, (f64, f64)>,
+    /// Figure state: Random numbers for the network graph; they don't alter the results of the model
+    #[cfg(feature = "net-graphics")]
+    layout_rng: rand_pcg::Pcg64,
+    /// Figure state: Health of the agents in the network graph, as posted by the simulation worker
+    #[cfg(feature = "net-graphics")]
+    graph_health: SecondaryMap<AgentKey, Health>,
+    /// Figure state: Links between agents in the network graph, as posted by the simulation worker
+    #[cfg(feature = "net-graphics")]
+    graph_links: std::vec::Vec<(AgentKey, AgentKey)>,
+    /// Number of time step results already posted to the main thread
+    posted_time_steps: usize,
+    /// Cell health already posted to the main thread
+    #[cfg(feature = "landscape")]
+    posted_cell_health: std::vec::Vec<Health>,
+    /// Snapshots of the model state, in order of time step
+    snapshots: std::vec::Vec<Snapshot>,
+    /// Number of time steps between snapshots; doubles when there are too many snapshots
+    snapshot_interval: u32,
+    /// Time step shown in the figures while rewinding; None shows the latest one.
+    /// The network graph always shows the latest time step.
+    #[cfg(feature = "graphics")]
+    view: Option<u32>,
+    /// Runs with pinned parameters that advance in lockstep with this one, from the same random seed
+    comparisons: std::vec::Vec<Model>,
+    /// Runs with the parameters of this one and other random seeds; they have no stop conditions
+    replicates: std::vec::Vec<Model>,
+    /// False in replicate runs, which keep only their latest results and no snapshots or landscape history
+    keep_history: bool,
+    /// True once the run has reported the stop condition that holds; later steps do nothing
+    stopped: bool,
+    /// Summary of the replicate runs in each time step
+    bands: std::vec::Vec<Band>,
+    /// Number of bands already posted to the main thread
+    posted_bands: usize,
+    /// Number of infection events already posted to the main thread
+    #[cfg(feature = "infection-events")]
+    posted_events: usize,
+    /// Figure state: Latest parameter sweep, drawn in the sweep panel
+    #[cfg(feature = "graphics")]
+    sweep: Option<Sweep>,
+}
+
+/// Maximum number of comparison runs next to the run that follows the page parameters
+const MAX_COMPARISONS: usize = 3;
+
+impl Model {
+    /// Initializes the model from the parameters in the page
+    fn new() -> Result<Self, AbmError> {
+        Self::with_size(js_n0() as usize, js_world_length())
+    }
+
+    /// Creates a model with `n0` agents on a landscape of `world_length` by `world_length` cells
+    fn with_size(n0: usize, world_length: i32) -> Result<Self, AbmError> {
+        // begin-similar-code 1
+        // Model parameter: Scale-free network parameter: new links per agent
+        #[cfg(feature = "net")]
+        let net_k: usize = 7;
+        // Model parameter: Dimensions of the virtual landscape, in number of cells
+        #[cfg(feature = "landscape")]
+        let coord = WrappingCoords2d::new(world_length, world_length)
+            .map_err(|e| AbmError::Parameter(format!("world size {}: {:?}", world_length, e)))?;
+        let initial_infection_distro = bernoulli("initial_infection_probability", 0.3)?;
+        // Normal distribution to choose cells in the landscape
+        #[cfg(feature = "landscape")]
+        let visit_distro =
+            Normal::new(50.0f32, 10f32).map_err(|e| AbmError::Internal(e.to_string()))?;
+        #[cfg(feature = "net")]
+        let link_distro = bernoulli("link_probability", 0.01)?;
+        let recovery_distro = bernoulli("recovery_probability", RECOVERY_PROBABILITY)?;
+        let survival_distro = bernoulli("survival_probability", SURVIVAL_PROBABILITY)?;
+        // end-similar-code 1
+        #[cfg(not(feature = "landscape"))]
+        let _ = world_length;
+        let scenario = Scenario::default();
+
+        // begin-similar-code 2
+        // Use Pcg64 for reproducible random numbers; change to thread_rng for production
+        // let mut rng = rand::thread_rng();
+        #[allow(clippy::unreadable_literal)]
+        let rng = rand_pcg::Pcg64::new(0xcafef00dd15ea5e5, 0xa02bdbf7bb3c0a7ac28fa16a64abf96);
+        // Model state: Agent health
+        let mut health = SlotMap::with_capacity_and_key(2 * n0);
+        // Model state: Bidirectional links between agents
+        #[cfg(feature = "net")]
+        let links = slotmap::SlotMap::with_capacity_and_key(n0 * n0);
+        // Model state: Health status of each cell in the landscape
+        #[cfg(feature = "landscape")]
+        let cell_health = vec![Health::S; coord.size()];
+        // Model state: Cell health storage for the next time step. This implements parallel updating of cells.
+        #[cfg(feature = "landscape")]
+        let next_cell_health = cell_health.clone();
+        // Model state: Remaining time steps of quarantine of each agent
+        #[cfg(feature = "contact-tracing")]
+        let quarantine = SecondaryMap::with_capacity(health.capacity());
+        // Model initialization: Agents
+        while health.len() < n0 {
+            let _k: AgentKey = health.insert(Health::S);
+        }
+        // end-similar-code 2
+        #[cfg(feature = "landscape")]
+        let cell_visits = vec![(0, 0); coord.size()];
+        Ok(Model {
+            scenario,
+            time_step: 0,
+            #[cfg(feature = "net")]
+            net_k,
+            #[cfg(feature = "landscape")]
+            coord,
+            initial_infection_distro,
+            #[cfg(feature = "landscape")]
+            visit_distro,
+            #[cfg(feature = "net")]
+            link_distro,
+            recovery_distro,
+            survival_distro,
+            rng,
+            health,
+            #[cfg(feature = "net")]
+            links,
+            #[cfg(feature = "landscape")]
+            cell_health,
+            #[cfg(feature = "landscape")]
+            next_cell_health,
+            #[cfg(feature = "contact-tracing")]
+            quarantine,
+            #[cfg(feature = "agent-metrics")]
+            flows: (0, 0, 0),
+            #[cfg(feature = "landscape")]
+            cell_visits,
+            #[cfg(feature = "graphics")]
+            geometry: Geometry::default(),
+            #[cfg(feature = "graphics")]
+            time_window: None,
+            #[cfg(feature = "net-graphics")]
+            graph_layout: SecondaryMap::new(),
+            #[cfg(feature = "net-graphics")]
+            layout_rng: rand_pcg::Pcg64::new(0x5851f42d4c957f2d, 0x14057b7ef767814f),
+            #[cfg(feature = "net-graphics")]
+            graph_health: SecondaryMap::new(),
+            #[cfg(feature = "net-graphics")]
+            graph_links: std::vec::Vec::new(),
+            posted_time_steps: 0,
+            #[cfg(feature = "landscape")]
+            posted_cell_health: std::vec::Vec::new(),
+            snapshots: std::vec::Vec::new(),
+            snapshot_interval: 10,
+            #[cfg(feature = "graphics")]
+            view: None,
+            comparisons: std::vec::Vec::new(),
+            replicates: std::vec::Vec::new(),
+            keep_history: true,
+            stopped: false,
+            bands: std::vec::Vec::new(),
+            posted_bands: 0,
+            #[cfg(feature = "infection-events")]
+            posted_events: 0,
+            #[cfg(feature = "graphics")]
+            sweep: None,
+        })
+    }
+
+    /// Reads the real-time parameters from the page
+    fn read_parameters(&mut self) -> Result<(), AbmError> {
+        self.scenario.infection_probability = js_infection_probability();
+        self.scenario.demography = Demography::from_js(&js_demography());
+        self.scenario.birth_rate = js_birth_rate();
+        self.scenario.immigration = js_immigration();
+        self.scenario.carrying_capacity = js_carrying_capacity();
+        #[cfg(feature = "behavior")]
+        {
+            self.scenario.risk_perception = RiskPerception::from_js(&js_risk_perception());
+            self.scenario.awareness = js_awareness();
+            self.scenario.compliance = js_compliance();
+        }
+        #[cfg(feature = "contact-tracing")]
+        {
+            self.scenario.detection_probability = js_detection_probability();
+            self.scenario.tracing_capacity = js_tracing_capacity();
+            self.scenario.quarantine_duration = js_quarantine_duration();
+        }
+        self.scenario.max_steps = js_max_steps();
+        self.scenario.stop_on_disease_extinction = js_stop_on_disease_extinction();
+        self.scenario.stop_on_population_extinction = js_stop_on_population_extinction();
+        self.scenario.prevalence_threshold = js_prevalence_threshold();
+        self.read_figure_parameters()
+    }
+
+    /// Reads the parameters that only affect the figures
+    fn read_figure_parameters(&mut self) -> Result<(), AbmError> {
+        #[cfg(feature = "graphics")]
+        {
+            self.scenario.dashboard = Dashboard::parse(&js_dashboard())?;
+            self.scenario.agent_series = Series::from_js(&js_agent_series());
+            self.scenario.proportional = js_proportional();
+            self.scenario.palette = Palette::from_js(&js_palette());
+            self.scenario.custom_colors = [
+                parse_color("susceptible colour", &js_susceptible_color())?,
+                parse_color("infected colour", &js_infected_color())?,
+            ];
+        }
+        #[cfg(feature = "net-graphics")]
+        {
+            self.scenario.graph_nodes = js_graph_nodes();
+            self.scenario.histogram_mode = HistogramMode::from_js(&js_histogram_mode());
+            self.scenario.net_series = NetSeries::from_js(&js_net_series());
+        }
+        #[cfg(feature = "net-metrics")]
+        {
+            self.scenario.net_metrics_interval = js_net_metrics_interval();
+        }
+        Ok(())
+    }
+
+    /// Runs one time step and records its results; returns false when a stop condition holds
+    fn step(&mut self) -> Result<bool, AbmError> {
+        self.take_snapshot();
+        let time_step = self.time_step;
+        #[cfg(feature = "net")]
+        let net_k = self.net_k;
+        let Model {
+            scenario,
+            #[cfg(feature = "landscape")]
+            coord,
+            initial_infection_distro,
+            #[cfg(feature = "landscape")]
+            visit_distro,
+            #[cfg(feature = "net")]
+            link_distro,
+            recovery_distro,
+            survival_distro,
+            rng,
+            health,
+            #[cfg(feature = "net")]
+            links,
+            #[cfg(feature = "landscape")]
+            cell_health,
+            #[cfg(feature = "landscape")]
+            next_cell_health,
+            #[cfg(feature = "contact-tracing")]
+            quarantine,
+            #[cfg(feature = "agent-metrics")]
+            flows,
+            #[cfg(feature = "landscape")]
+            cell_visits,
+            #[cfg(feature = "landscape-graphics")]
+            keep_history,
+            stopped,
+            ..
+        } = &mut *self;
+        let infection_distro = bernoulli("infection_probability", scenario.infection_probability)?;
+        let birth_distro = bernoulli("birth_rate", scenario.birth_rate)?;
+        // Stop conditions: The final frame of the run stays visible
+        let stop_reason = {
+            let i = health.values().filter(|&&h| h == Health::I).count();
+            #[cfg(feature = "landscape")]
+            let c_i = cell_health.iter().filter(|&&h| h == Health::I).count();
+            #[cfg(not(feature = "landscape"))]
+            let c_i = 0;
+            if scenario.max_steps > 0 && time_step >= scenario.max_steps {
+                Some(StopReason::MaxSteps)
+            } else if scenario.stop_on_population_extinction && health.is_empty() {
+                Some(StopReason::PopulationExtinction)
+            } else if scenario.stop_on_disease_extinction && time_step > 0 && i == 0 && c_i == 0 {
+                // The disease emerges during the first time step
+                Some(StopReason::DiseaseExtinction)
+            } else if scenario.prevalence_threshold > 0.0
+                && !health.is_empty()
+                && i as f64 / health.len() as f64 >= scenario.prevalence_threshold
+            {
+                Some(StopReason::PrevalenceThreshold)
+            } else {
+                None
+            }
+        };
+        if let Some(reason) = stop_reason {
+            // The stop condition was already reported
+            if *stopped {
+                return Ok(false);
+            }
+            *stopped = true;
+            if scenario.id == 0 {
+                js_message(&format!(
+                    "The simulation stopped at time step {} because {}.",
+                    time_step,
+                    reason.description()
+                ));
+            } else {
+                js_message(&format!(
+                    "{} stopped at time step {} because {}.",
+                    scenario.label(),
+                    time_step,
+                    reason.description()
+                ));
+            }
+            if let Some(tsr) = scenario.time_series.last() {
+                if tsr.time_step == time_step {
+                    // The final frame is already visible
+                    return Ok(false);
+                }
+            }
+        } else {
+            // New parameters resume a stopped run
+            *stopped = false;
+        }
+        let mut time_step_results: TimeStepResults = Default::default();
+
+        // begin-similar-code 3
+        // Initialization of this time step: Network seed
+        #[cfg(feature = "net")]
+        {
+            if links.is_empty() {
+                let mut h_it = health.keys();
+                if let (Some(key0), Some(key1)) = (h_it.next(), h_it.next()) {
+                    let _link_id: LinkKey = links.insert((key0, key1));
+                }
+            }
+            // Initialization of this time step: Network
+            let keys_vec: Vec<AgentKey> = health.keys().collect();
+            let mut idx_map = SecondaryMap::with_capacity(health.capacity());
+            let mut weights_vec: Vec<i32> = {
+                let mut weights_map = SecondaryMap::with_capacity(health.capacity());
+                keys_vec.iter().enumerate().for_each(|(idx, &k)| {
+                    weights_map.insert(k, 0);
+                    idx_map.insert(k, idx);
+                });
+                links.values().for_each(|&(key0, key1)| {
+                    weights_map[key0] += 1;
+                    weights_map[key1] += 1;
+                });
+                keys_vec.iter().map(|&k| weights_map[k]).collect()
+            };
+            // A stopped run records its final time step without growing the network
+            if stop_reason.is_none() {
+                keys_vec
+                    .iter()
+                    .enumerate()
+                    .try_for_each(|(agent_idx, &agent_key)| {
+                        let new_links = if weights_vec[agent_idx] == 0 {
+                            net_k
+                        } else if link_distro.sample(rng) {
+                            1
+                        } else {
+                            0
+                        };
+                        if new_links > 0 {
+                            let mut weights_tmp = weights_vec.clone();
+                            // This agent cannot make a link to itself; set its weight to 0.
+                            weights_tmp[agent_idx] = 0;
+                            // Friends are ineligible for a new link; set friends' weights to 0.
+                            links.values().for_each(|&(key0, key1)| {
+                                if key0 == agent_key {
+                                    weights_tmp[idx_map[key1]] = 0;
+                                }
+                                if key1 == agent_key {
+                                    weights_tmp[idx_map[key0]] = 0;
+                                }
+                            });
+                            match WeightedIndex::new(weights_tmp) {
+                                Ok(mut dist) => {
+                                    let mut k = 0;
+                                    loop {
+                                        let friend_idx = dist.sample(rng);
+                                        links.insert((agent_key, keys_vec[friend_idx]));
+                                        weights_vec[agent_idx] += 1;
+                                        weights_vec[friend_idx] += 1;
+                                        k += 1;
+                                        if k == new_links {
+                                            break;
+                                        }
+                                        // Make friend ineligible for a new link; set its weight to 0.
+                                        if dist.update_weights(&[(friend_idx, &0)]).is_err() {
+                                            break;
+                                        }
+                                    }
+                                }
+                                Err(WeightedError::AllWeightsZero) => {}
+                                Err(e) => {
+                                    return Err(AbmError::Internal(format!("OsXJWc0sHx: {}", e)));
+                                }
+                            }
+                        }
+                        Ok(())
+                    })?;
+            }
+            // Model measurements: Network
+            #[cfg(feature = "net-metrics")]
+            {
+                time_step_results.d_s = match keys_vec
+                    .iter()
+                    .zip(weights_vec.iter())
+                    .filter(|(&k, _w)| health[k] == Health::S)
                     .max_by_key(|(_k, &w)| w)
                 {
                     Some((_k, &w)) => w,
@@ -294,6 +2362,11 @@ pub fn rs_deploy_scenario() {
                     Some((_k, &w)) => w,
                     None => 0,
                 };
+                if scenario.net_metrics_interval > 0
+                    && time_step % scenario.net_metrics_interval == 0
+                {
+                    time_step_results.net = Some(NetMetrics::measure(health, links));
+                }
             }
             #[cfg(feature = "net-graphics")]
             {
@@ -303,10 +2376,10 @@ pub fn rs_deploy_scenario() {
                         .entry(weight)
                         .or_insert(0) += 1;
                 }
+                // Both are kept so that the histogram mode can change during the simulation
                 for (&weight, &frequency) in &time_step_results.degree_histogram {
-                    if compress_histogram {
-                        scenario.histogram_degrees_set.insert(weight);
-                    } else if scenario.histogram_max_degree < weight {
+                    scenario.histogram_degrees_set.insert(weight);
+                    if scenario.histogram_max_degree < weight {
                         scenario.histogram_max_degree = weight;
                     }
                     if scenario.histogram_height < frequency {
@@ -315,6 +2388,115 @@ pub fn rs_deploy_scenario() {
                 }
             }
         }
+        // Dynamics: Susceptible agents perceive the risk of infection; some of them self-isolate
+        #[cfg(feature = "behavior")]
+        let isolated = if stop_reason.is_some() || scenario.awareness * scenario.compliance == 0.0 {
+            // Nobody self-isolates, or the run stopped; skipping the block keeps the random numbers
+            SecondaryMap::new()
+        } else {
+            let global_prevalence = health.values().filter(|&&h| h == Health::I).count() as f64
+                / core::cmp::max(health.len(), 1) as f64;
+            // Number of neighbours and number of infected neighbours of each agent
+            #[cfg_attr(not(feature = "net"), allow(unused_mut))]
+            let mut neighbourhood: SecondaryMap<AgentKey, (u32, u32)> =
+                SecondaryMap::with_capacity(health.capacity());
+            #[cfg(feature = "net")]
+            if scenario.risk_perception == RiskPerception::Neighbours {
+                links.values().for_each(|&(key0, key1)| {
+                    // Links to agents that have died are skipped
+                    let (Some(&health0), Some(&health1)) = (health.get(key0), health.get(key1))
+                    else {
+                        return;
+                    };
+                    if let Some(entry) = neighbourhood.entry(key0) {
+                        let (total, infected) = entry.or_insert((0, 0));
+                        *total += 1;
+                        if health1 == Health::I {
+                            *infected += 1;
+                        }
+                    }
+                    if let Some(entry) = neighbourhood.entry(key1) {
+                        let (total, infected) = entry.or_insert((0, 0));
+                        *total += 1;
+                        if health0 == Health::I {
+                            *infected += 1;
+                        }
+                    }
+                });
+            }
+            let mut isolated = SecondaryMap::with_capacity(health.capacity());
+            health
+                .iter()
+                .filter(|(_k, &h)| h == Health::S)
+                .try_for_each(|(k, _h)| -> Result<(), AbmError> {
+                    let perceived_prevalence = match neighbourhood.get(k) {
+                        Some(&(total, infected)) => infected as f64 / total as f64,
+                        None => global_prevalence,
+                    };
+                    let isolation_probability =
+                        scenario.compliance * (scenario.awareness * perceived_prevalence).min(1.0);
+                    if isolation_probability > 0.0
+                        && bernoulli("isolation_probability", isolation_probability)?.sample(rng)
+                    {
+                        isolated.insert(k, ());
+                    }
+                    Ok(())
+                })?;
+            isolated
+        };
+        // Without the network and the landscape, agents have no contacts to avoid
+        #[cfg(all(
+            feature = "behavior",
+            not(any(feature = "net", feature = "landscape", feature = "agent-metrics"))
+        ))]
+        let _ = isolated;
+        // Interventions: Testing detects infected agents; tracers quarantine them and their network neighbours
+        #[cfg(feature = "contact-tracing")]
+        let detected = if stop_reason.is_some() {
+            // A stopped run keeps its quarantines for a resumed run
+            0
+        } else {
+            quarantine.retain(|agent_key, remaining_steps: &mut u32| {
+                *remaining_steps -= 1;
+                *remaining_steps > 0 && health.contains_key(agent_key)
+            });
+            // Detected agents spend at least one time step in quarantine, or detection would have no effect
+            let quarantine_duration = scenario.quarantine_duration.max(1);
+            let mut detected: Vec<AgentKey> = if scenario.detection_probability > 0.0 {
+                let detection_distro =
+                    bernoulli("detection_probability", scenario.detection_probability)?;
+                health
+                    .iter()
+                    .filter(|&(k, &h)| {
+                        h == Health::I
+                            && !quarantine.contains_key(k)
+                            && detection_distro.sample(rng)
+                    })
+                    .map(|(k, _h)| k)
+                    .collect()
+            } else {
+                // Skipping the sampling keeps the random numbers of runs without testing
+                Vec::new()
+            };
+            let detected_len = detected.len();
+            // Tracers have limited capacity; they follow up a random subset of the detected agents
+            detected.shuffle(rng);
+            detected.truncate(scenario.tracing_capacity as usize);
+            let mut traced = SecondaryMap::with_capacity(health.capacity());
+            for &agent_key in &detected {
+                traced.insert(agent_key, ());
+                quarantine.insert(agent_key, quarantine_duration);
+            }
+            links.values().for_each(|&(key0, key1)| {
+                if traced.contains_key(key0) {
+                    quarantine.insert(key1, quarantine_duration);
+                }
+                if traced.contains_key(key1) {
+                    quarantine.insert(key0, quarantine_duration);
+                }
+            });
+            detected_len
+        };
         // Model measurements: agents
         {
             time_step_results.time_step = time_step;
@@ -325,6 +2507,32 @@ pub fn rs_deploy_scenario() {
                     Health::S => time_step_results.s += 1,
                     Health::I => time_step_results.i += 1,
                 });
+                (
+                    time_step_results.incidence,
+                    time_step_results.deaths,
+                    time_step_results.births,
+                ) = *flows;
+                // A stopped run makes no decisions in its final time step; it repeats the latest ones
+                #[cfg(any(feature = "behavior", feature = "contact-tracing"))]
+                let previous = scenario
+                    .time_series
+                    .iter()
+                    .rev()
+                    .find(|tsr| tsr.time_step < time_step)
+                    .filter(|_| stop_reason.is_some());
+                #[cfg(feature = "behavior")]
+                {
+                    time_step_results.a = previous.map_or(isolated.len() as u32, |tsr| tsr.a);
+                }
+                #[cfg(feature = "contact-tracing")]
+                {
+                    time_step_results.t_d = previous.map_or(detected as u32, |tsr| tsr.t_d);
+                    time_step_results.t_q = quarantine.len() as u32;
+                    time_step_results.t_m = health
+                        .iter()
+                        .filter(|&(k, &h)| h == Health::I && !quarantine.contains_key(k))
+                        .count() as u32;
+                }
             }
             #[cfg(feature = "landscape-metrics")]
             {
@@ -336,122 +2544,1203 @@ pub fn rs_deploy_scenario() {
                 if scenario.agent_time_series_height < time_step_results.n {
                     scenario.agent_time_series_height = time_step_results.n;
                 }
+                if scenario.demography == Demography::Logistic
+                    && scenario.agent_time_series_height < scenario.carrying_capacity
+                {
+                    scenario.agent_time_series_height = scenario.carrying_capacity;
+                }
             }
             #[cfg(feature = "landscape-graphics")]
             {
                 if scenario.cell_time_series_height < time_step_results.c_i {
                     scenario.cell_time_series_height = time_step_results.c_i;
                 }
-                time_step_results.cell_health = cell_health.clone();
+                if *keep_history {
+                    time_step_results.cell_health = cell_health.clone();
+                }
+            }
+            // A run that resumes after a stop measures its last time step again
+            if let Some(tsr) = scenario.time_series.last_mut() {
+                if tsr.time_step == time_step {
+                    *tsr = time_step_results.clone();
+                } else {
+                    scenario.time_series.push(time_step_results.clone());
+                }
+            } else {
+                scenario.time_series.push(time_step_results.clone());
+            }
+            #[cfg(feature = "landscape-graphics")]
+            forget_old_landscape(&mut scenario.time_series);
+        }
+        if stop_reason.is_none() {
+            // Dynamics: infection spreads
+            {
+                // Model state: Agent health the next time step
+                let mut next_health = SecondaryMap::with_capacity(health.capacity());
+                // Model state: Source of each infection during this time step
+                #[cfg(feature = "infection-events")]
+                let mut sources = SecondaryMap::with_capacity(health.capacity());
+                #[cfg(feature = "net")]
+                links.values().for_each(|&(key0, key1)| {
+                    let h0 = health[key0];
+                    let h1 = health[key1];
+                    // Self-isolating agents avoid their contacts
+                    #[cfg(feature = "behavior")]
+                    if isolated.contains_key(key0) || isolated.contains_key(key1) {
+                        return;
+                    }
+                    // Links of quarantined agents are inactive
+                    #[cfg(feature = "contact-tracing")]
+                    if quarantine.contains_key(key0) || quarantine.contains_key(key1) {
+                        return;
+                    }
+                    if h0 == Health::S && h1 == Health::I && infection_distro.sample(rng) {
+                        next_health.insert(key0, Health::I);
+                        #[cfg(feature = "infection-events")]
+                        sources.insert(key0, Source::Neighbor(key1));
+                    }
+                    if h1 == Health::S && h0 == Health::I && infection_distro.sample(rng) {
+                        next_health.insert(key1, Health::I);
+                        #[cfg(feature = "infection-events")]
+                        sources.insert(key1, Source::Neighbor(key0));
+                    }
+                });
+                if time_step == 0 {
+                    health.iter().for_each(|(k, &h)| {
+                        if h == Health::S && initial_infection_distro.sample(rng) {
+                            next_health.insert(k, Health::I);
+                            #[cfg(feature = "infection-events")]
+                            sources.insert(k, Source::Seed);
+                        }
+                    });
+                }
+                #[cfg(feature = "landscape")]
+                cell_visits.iter_mut().for_each(|visits| *visits = (0, 0));
+                health.iter().for_each(|(k, &h)| {
+                    // Choose a random cell to visit
+                    #[cfg(feature = "landscape")]
+                    let x = visit_distro.sample(rng) as i32;
+                    #[cfg(feature = "landscape")]
+                    let y = visit_distro.sample(rng) as i32;
+                    #[cfg(feature = "landscape")]
+                    let idx = coord.index(x, y);
+                    match h {
+                        Health::S => {
+                            #[cfg(feature = "landscape")]
+                            {
+                                // Self-isolating agents stay at home instead of visiting the cell
+                                #[cfg(feature = "behavior")]
+                                if isolated.contains_key(k) {
+                                    return;
+                                }
+                                // Quarantined agents do not visit the landscape
+                                #[cfg(feature = "contact-tracing")]
+                                if quarantine.contains_key(k) {
+                                    return;
+                                }
+                                cell_visits[idx].0 += 1;
+                                if cell_health[idx] == Health::I && infection_distro.sample(rng) {
+                                    // Cell infects agent
+                                    next_health.insert(k, Health::I);
+                                    #[cfg(feature = "infection-events")]
+                                    sources.insert(k, Source::Cell(idx as u32));
+                                }
+                            }
+                        }
+                        Health::I => {
+                            #[cfg(feature = "landscape")]
+                            {
+                                // Quarantined agents do not visit the landscape
+                                #[cfg(feature = "contact-tracing")]
+                                let visits = !quarantine.contains_key(k);
+                                #[cfg(not(feature = "contact-tracing"))]
+                                let visits = true;
+                                if visits {
+                                    cell_visits[idx].1 += 1;
+                                }
+                                if visits
+                                    && cell_health[idx] == Health::S
+                                    && infection_distro.sample(rng)
+                                {
+                                    // Agent infects cell
+                                    next_cell_health[idx] = Health::I;
+                                }
+                            }
+                            if recovery_distro.sample(rng) {
+                                next_health.insert(k, Health::S);
+                            }
+                        }
+                    };
+                });
+                // Dynamics: Disease spreads across cells and infectious cells recover
+                #[cfg(feature = "landscape")]
+                coord.for_each8(
+                    |this_cell_index, neighbors| match cell_health[this_cell_index] {
+                        Health::S => {
+                            for neighbor_index in neighbors {
+                                if cell_health[*neighbor_index] == Health::I
+                                    && infection_distro.sample(rng)
+                                {
+                                    next_cell_health[this_cell_index] = Health::I;
+                                    break;
+                                }
+                            }
+                        }
+                        Health::I => {
+                            if recovery_distro.sample(rng) {
+                                next_cell_health[this_cell_index] = Health::S;
+                            }
+                        }
+                    },
+                );
+                // Dynamics: After spreading the infection, some infectious agents die
+                #[cfg(feature = "agent-metrics")]
+                let n_before_deaths = health.len();
+                health.retain(|_agent_key, h| match h {
+                    Health::S => true,
+                    Health::I => survival_distro.sample(rng),
+                });
+                #[cfg(feature = "agent-metrics")]
+                {
+                    flows.0 = 0;
+                    flows.1 = (n_before_deaths - health.len()) as u32;
+                }
+                // Dynamics: Remaining agents update in parallel
+                next_health.iter().for_each(|(k, &next_h)| {
+                    if let Some(h) = health.get_mut(k) {
+                        #[cfg(feature = "agent-metrics")]
+                        if *h == Health::S && next_h == Health::I {
+                            flows.0 += 1;
+                            #[cfg(feature = "infection-events")]
+                            if let Some(&source) = sources.get(k) {
+                                scenario.infection_events.push(InfectionEvent {
+                                    time_step,
+                                    infectee: k,
+                                    source,
+                                });
+                            }
+                        }
+                        *h = next_h;
+                    }
+                });
+                // Dynamics: cells update in parallel
+                #[cfg(feature = "landscape")]
+                {
+                    *cell_health = next_cell_health.clone();
+                }
+            }
+            // Dynamics: Prune network
+            #[cfg(feature = "net")]
+            links.retain(|_link_key, (key0, key1)| {
+                health.contains_key(*key0) && health.contains_key(*key1)
+            });
+            // Dynamics: New agents emerge
+            let nb = match scenario.demography {
+                Demography::SusceptibleBirths => health
+                    .values()
+                    .filter(|&&h| h == Health::S && birth_distro.sample(rng))
+                    .count(),
+                Demography::Immigration => scenario.immigration as usize,
+                Demography::Logistic => {
+                    let crowding = health.len() as f64 / scenario.carrying_capacity as f64;
+                    let logistic_distro = bernoulli(
+                        "logistic_birth_rate",
+                        (scenario.birth_rate * (1.0 - crowding)).max(0.0),
+                    )?;
+                    health
+                        .values()
+                        .filter(|_| logistic_distro.sample(rng))
+                        .count()
+                }
+                Demography::AllBirths => {
+                    health.values().filter(|_| birth_distro.sample(rng)).count()
+                }
+            };
+            for _ in 0..nb {
+                health.insert(Health::S);
+            }
+            #[cfg(feature = "agent-metrics")]
+            {
+                flows.2 = nb as u32;
+            }
+        }
+        // end-similar-code 3
+        #[cfg(feature = "net-graphics")]
+        if scenario.dashboard.contains(Panel::Network) {
+            self.update_graph_layout();
+        }
+        if stop_reason.is_some() {
+            #[cfg(feature = "agent-metrics")]
+            if self.scenario.id == 0 {
+                if let Some(summary) = Summary::of(&self.scenario.time_series) {
+                    js_message(&summary.lines().join(". "));
+                }
+            }
+            return Ok(false);
+        }
+        self.time_step += 1;
+        Ok(true)
+    }
+
+    /// Samples the agents shown in the network graph and advances its force-directed layout.
+    /// Positions carry over between time steps, so the picture only drifts as the network changes.
+    #[cfg(feature = "net-graphics")]
+    fn update_graph_layout(&mut self) {
+        let iterations = 10;
+        let Model {
+            scenario,
+            health,
+            links,
+            graph_layout,
+            layout_rng,
+            ..
+        } = self;
+        // Sampling: Agents stay in the graph until they die or the node cap shrinks
+        graph_layout.retain(|agent_key, _| health.contains_key(agent_key));
+        let graph_nodes = scenario.graph_nodes as usize;
+        let excess: std::vec::Vec<AgentKey> = graph_layout.keys().skip(graph_nodes).collect();
+        for agent_key in excess {
+            graph_layout.remove(agent_key);
+        }
+        if graph_layout.len() < graph_nodes {
+            let mut candidates: std::vec::Vec<AgentKey> = health
+                .keys()
+                .filter(|&agent_key| !graph_layout.contains_key(agent_key))
+                .collect();
+            let (sample, _) =
+                candidates.partial_shuffle(layout_rng, graph_nodes - graph_layout.len());
+            let mut new_nodes = SecondaryMap::with_capacity(sample.len());
+            for &agent_key in sample.iter() {
+                new_nodes.insert(agent_key, ());
+                graph_layout.insert(agent_key, (layout_rng.gen(), layout_rng.gen()));
+            }
+            // New agents start next to a linked agent that is already in place
+            for &(key0, key1) in links.values() {
+                for (new_key, old_key) in [(key0, key1), (key1, key0)] {
+                    if new_nodes.contains_key(new_key) && !new_nodes.contains_key(old_key) {
+                        if let Some(&(x, y)) = graph_layout.get(old_key) {
+                            let jitter = 0.02;
+                            graph_layout[new_key] = (
+                                x + jitter * (layout_rng.gen::<f64>() - 0.5),
+                                y + jitter * (layout_rng.gen::<f64>() - 0.5),
+                            );
+                            new_nodes.remove(new_key);
+                        }
+                    }
+                }
+            }
+        }
+        let n = graph_layout.len();
+        if n == 0 {
+            return;
+        }
+        // Fruchterman-Reingold forces with a low, fixed temperature
+        let node_keys: std::vec::Vec<AgentKey> = graph_layout.keys().collect();
+        let mut node_idx = SecondaryMap::with_capacity(n);
+        node_keys.iter().enumerate().for_each(|(idx, &agent_key)| {
+            node_idx.insert(agent_key, idx);
+        });
+        let edges: std::vec::Vec<(usize, usize)> = links
+            .values()
+            .filter_map(|&(key0, key1)| Some((*node_idx.get(key0)?, *node_idx.get(key1)?)))
+            .collect();
+        let mut pos: std::vec::Vec<(f64, f64)> = node_keys
+            .iter()
+            .map(|&agent_key| graph_layout[agent_key])
+            .collect();
+        let k = (1.0 / n as f64).sqrt();
+        let temperature = 0.1 * k;
+        let gravity = 0.1 * n as f64 * k;
+        for _ in 0..iterations {
+            let mut disp = vec![(0.0, 0.0); n];
+            for i in 0..n {
+                for j in (i + 1)..n {
+                    let dx = pos[i].0 - pos[j].0;
+                    let dy = pos[i].1 - pos[j].1;
+                    let force = k * k / (dx * dx + dy * dy).max(1e-9);
+                    disp[i].0 += dx * force;
+                    disp[i].1 += dy * force;
+                    disp[j].0 -= dx * force;
+                    disp[j].1 -= dy * force;
+                }
+            }
+            for &(i, j) in edges.iter() {
+                let dx = pos[i].0 - pos[j].0;
+                let dy = pos[i].1 - pos[j].1;
+                let force = (dx * dx + dy * dy).sqrt() / k;
+                disp[i].0 -= dx * force;
+                disp[i].1 -= dy * force;
+                disp[j].0 += dx * force;
+                disp[j].1 += dy * force;
+            }
+            pos.iter_mut().zip(disp.iter()).for_each(|(p, d)| {
+                // Gravity keeps disconnected agents in view
+                let dx = d.0 - gravity * (p.0 - 0.5);
+                let dy = d.1 - gravity * (p.1 - 0.5);
+                let length = (dx * dx + dy * dy).sqrt();
+                if length > 0.0 {
+                    let step = length.min(temperature) / length;
+                    p.0 = (p.0 + dx * step).clamp(0.0, 1.0);
+                    p.1 = (p.1 + dy * step).clamp(0.0, 1.0);
+                }
+            });
+        }
+        node_keys.iter().zip(pos).for_each(|(&agent_key, p)| {
+            graph_layout[agent_key] = p;
+        });
+    }
+
+    /// Copies the model state at the start of the current time step
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            time_step: self.time_step,
+            rng: self.rng.clone(),
+            health: self.health.clone(),
+            #[cfg(feature = "net")]
+            links: self.links.clone(),
+            #[cfg(feature = "landscape")]
+            cell_health: self.cell_health.clone(),
+            #[cfg(feature = "landscape")]
+            next_cell_health: self.next_cell_health.clone(),
+            #[cfg(feature = "contact-tracing")]
+            quarantine: self.quarantine.clone(),
+            #[cfg(feature = "agent-metrics")]
+            flows: self.flows,
+            #[cfg(feature = "landscape")]
+            cell_visits: self.cell_visits.clone(),
+        }
+    }
+
+    /// Encodes the complete simulation state in the saved run format
+    fn save(&self) -> Result<std::vec::Vec<u8>, AbmError> {
+        let mut bytes = SAVED_RUN_MAGIC.to_vec();
+        bytes.extend_from_slice(&SAVED_RUN_VERSION.to_le_bytes());
+        let saved_run = SavedRun {
+            #[cfg(feature = "landscape")]
+            world_length: self.coord.width(),
+            scenario: self.scenario.clone(),
+            state: self.snapshot(),
+            snapshot_interval: self.snapshot_interval,
+            vacant_agents: vacant_slots(&self.health, Health::S),
+            #[cfg(feature = "net")]
+            vacant_links: vacant_slots(&self.links, Default::default()),
+        };
+        bincode::serialize_into(&mut bytes, &saved_run)
+            .map_err(|e| AbmError::Internal(format!("saving the run: {}", e)))?;
+        Ok(bytes)
+    }
+
+    /// Decodes a saved run; the model continues from the time step where it was saved
+    fn load(bytes: &[u8]) -> Result<Self, AbmError> {
+        let not_saved_run = || AbmError::Parameter("the file is not a saved run".to_string());
+        let body = bytes
+            .strip_prefix(SAVED_RUN_MAGIC.as_slice())
+            .ok_or_else(not_saved_run)?;
+        let version = body
+            .get(..4)
+            .and_then(|version| version.try_into().ok())
+            .map(u32::from_le_bytes)
+            .ok_or_else(not_saved_run)?;
+        if version != SAVED_RUN_VERSION {
+            return Err(AbmError::Parameter(format!(
+                "saved run format {} is not supported; this version reads format {}",
+                version, SAVED_RUN_VERSION
+            )));
+        }
+        let mut saved_run: SavedRun = bincode::deserialize(&body[4..]).map_err(|e| {
+            AbmError::Parameter(format!(
+                "the saved run does not match the features of this build: {}",
+                e
+            ))
+        })?;
+        #[cfg(feature = "landscape")]
+        let world_length = saved_run.world_length;
+        #[cfg(not(feature = "landscape"))]
+        let world_length = 0;
+        // New agents and links take the same keys as in the saved run
+        let state = &mut saved_run.state;
+        state.health = refill_in_order(
+            std::mem::take(&mut state.health),
+            &saved_run.vacant_agents,
+            Health::S,
+        )
+        .ok_or_else(not_saved_run)?;
+        #[cfg(feature = "net")]
+        {
+            state.links = refill_in_order(
+                std::mem::take(&mut state.links),
+                &saved_run.vacant_links,
+                Default::default(),
+            )
+            .ok_or_else(not_saved_run)?;
+        }
+        let mut model = Model::with_size(0, world_length)?;
+        let time_step = saved_run.state.time_step;
+        model.scenario = saved_run.scenario;
+        model.snapshot_interval = saved_run.snapshot_interval;
+        model.snapshots = vec![saved_run.state];
+        model.restore(time_step).ok_or_else(not_saved_run)?;
+        Ok(model)
+    }
+
+    /// Model parameters, named as in src/app.js, so that the page shows those of a loaded run
+    fn page_parameters(&self) -> Result<js_sys::Object, AbmError> {
+        let parameters = js_sys::Object::new();
+        let set = |key: &str, value: JsValue| {
+            js_sys::Reflect::set(&parameters, &JsValue::from_str(key), &value)
+                .map(|_| ())
+                .map_err(|_| AbmError::Internal(format!("setting the page parameter {}", key)))
+        };
+        let scenario = &self.scenario;
+        #[cfg(feature = "landscape")]
+        set("world_length", self.coord.width().into())?;
+        set(
+            "infection_probability",
+            scenario.infection_probability.into(),
+        )?;
+        set("demography", scenario.demography.to_js().into())?;
+        set("birth_rate", scenario.birth_rate.into())?;
+        set("immigration", scenario.immigration.into())?;
+        set("carrying_capacity", scenario.carrying_capacity.into())?;
+        #[cfg(feature = "behavior")]
+        {
+            set("risk_perception", scenario.risk_perception.to_js().into())?;
+            set("awareness", scenario.awareness.into())?;
+            set("compliance", scenario.compliance.into())?;
+        }
+        #[cfg(feature = "contact-tracing")]
+        {
+            set(
+                "detection_probability",
+                scenario.detection_probability.into(),
+            )?;
+            set("tracing_capacity", scenario.tracing_capacity.into())?;
+            set("quarantine_duration", scenario.quarantine_duration.into())?;
+        }
+        set("max_steps", scenario.max_steps.into())?;
+        set(
+            "stop_on_disease_extinction",
+            scenario.stop_on_disease_extinction.into(),
+        )?;
+        set(
+            "stop_on_population_extinction",
+            scenario.stop_on_population_extinction.into(),
+        )?;
+        set("prevalence_threshold", scenario.prevalence_threshold.into())?;
+        Ok(parameters)
+    }
+
+    /// Saves the model state every `snapshot_interval` time steps.
+    /// When there are too many snapshots, keeps every other one and doubles the interval.
+    fn take_snapshot(&mut self) {
+        if !self.keep_history
+            || self.time_step % self.snapshot_interval != 0
+            || self
+                .snapshots
+                .last()
+                .map_or(false, |snapshot| snapshot.time_step == self.time_step)
+        {
+            return;
+        }
+        self.snapshots.push(self.snapshot());
+        if self.snapshots.len() > MAX_SNAPSHOTS {
+            self.snapshot_interval *= 2;
+            let interval = self.snapshot_interval;
+            self.snapshots
+                .retain(|snapshot| snapshot.time_step % interval == 0);
+        }
+    }
+
+    /// Rewinds to the latest snapshot at or before `time_step`, discarding the later results.
+    /// Returns the time step of the snapshot.
+    fn restore(&mut self, time_step: u32) -> Option<u32> {
+        let position = self
+            .snapshots
+            .iter()
+            .rposition(|snapshot| snapshot.time_step <= time_step)?;
+        self.snapshots.truncate(position + 1);
+        let snapshot = self.snapshots[position].clone();
+        self.time_step = snapshot.time_step;
+        self.rng = snapshot.rng;
+        self.health = snapshot.health;
+        #[cfg(feature = "net")]
+        {
+            self.links = snapshot.links;
+        }
+        #[cfg(feature = "landscape")]
+        {
+            self.cell_health = snapshot.cell_health;
+            self.next_cell_health = snapshot.next_cell_health;
+            self.cell_visits = snapshot.cell_visits;
+        }
+        #[cfg(feature = "contact-tracing")]
+        {
+            self.quarantine = snapshot.quarantine;
+        }
+        #[cfg(feature = "agent-metrics")]
+        {
+            self.flows = snapshot.flows;
+        }
+        #[cfg(feature = "net-graphics")]
+        self.graph_layout.clear();
+        self.stopped = false;
+        self.scenario
+            .time_series
+            .retain(|tsr| tsr.time_step < snapshot.time_step);
+        // The next frame carries the whole time series
+        self.posted_time_steps = 0;
+        for run in self.comparisons.iter_mut() {
+            run.restore(snapshot.time_step);
+        }
+        // Replicate runs keep no snapshots; they continue from the restored state of this run
+        let seeds = 1..=self.replicates.len() as u64;
+        let restored = &self.snapshots[position];
+        let replicates = seeds
+            .filter_map(|seed| self.replicate(seed, restored).ok())
+            .collect();
+        self.replicates = replicates;
+        self.bands
+            .retain(|band| band.time_step < snapshot.time_step);
+        self.posted_bands = 0;
+        #[cfg(feature = "infection-events")]
+        {
+            self.scenario
+                .infection_events
+                .retain(|event| event.time_step < snapshot.time_step);
+            self.posted_events = 0;
+        }
+        Some(snapshot.time_step)
+    }
+
+    /// Runs up to `steps` time steps, so that the page draws only the last one; returns false when a stop condition holds
+    fn step_n(&mut self, steps: u32) -> Result<bool, AbmError> {
+        self.read_parameters()?;
+        let mut running = true;
+        for _ in 0..steps {
+            if !self.step()? {
+                running = false;
+                break;
+            }
+        }
+        // Comparison runs keep their pinned parameters and catch up with this one
+        for run in self.comparisons.iter_mut() {
+            while run.time_step < self.time_step && !run.stopped() {
+                run.step()?;
+            }
+        }
+        Ok(running)
+    }
+
+    /// Adds `replicates` runs with the parameters of this one and a different random seed each
+    fn add_replicates(&mut self, replicates: u32) -> Result<(), AbmError> {
+        if replicates > MAX_REPLICATES {
+            return Err(AbmError::Parameter(format!(
+                "the number of replicates must be at most {}",
+                MAX_REPLICATES
+            )));
+        }
+        for seed in 1..=replicates as u64 {
+            let run = self.headless(seed)?;
+            self.replicates.push(run);
+        }
+        Ok(())
+    }
+
+    /// Creates a run with the size and dynamics of this one and the random seed `seed`.
+    /// It has no stop conditions and draws no figures.
+    fn headless(&self, seed: u64) -> Result<Model, AbmError> {
+        #[cfg(feature = "landscape")]
+        let world_length = self.coord.width();
+        #[cfg(not(feature = "landscape"))]
+        let world_length = 0;
+        let mut run = Model::with_size(self.health.len(), world_length)?;
+        run.rng = rand_pcg::Pcg64::seed_from_u64(seed);
+        run.scenario.copy_dynamics(&self.scenario);
+        run.keep_history = false;
+        Ok(run)
+    }
+
+    /// Creates a replicate run that continues from `snapshot` of this run with the random seed `seed`
+    fn replicate(&self, seed: u64, snapshot: &Snapshot) -> Result<Model, AbmError> {
+        let mut run = self.headless(seed)?;
+        run.snapshots = vec![snapshot.clone()];
+        run.restore(snapshot.time_step);
+        run.snapshots.clear();
+        run.rng = rand_pcg::Pcg64::seed_from_u64(seed);
+        Ok(run)
+    }
+
+    /// Advances the replicate run that lags most by one time step, with the current parameters of this run.
+    /// Returns the bands of the time steps that all replicate runs finished since the previous bands were posted,
+    /// or None when the replicate runs caught up with this run.
+    fn step_replicate(&mut self) -> Result<Option<std::vec::Vec<Band>>, AbmError> {
+        let time_step = self.time_step;
+        let Some(run) = self
+            .replicates
+            .iter_mut()
+            .filter(|run| run.time_step < time_step)
+            .min_by_key(|run| run.time_step)
+        else {
+            return Ok(None);
+        };
+        run.scenario.copy_dynamics(&self.scenario);
+        run.step()?;
+        // The bands only need the latest results
+        let old_results = run.scenario.time_series.len().saturating_sub(1);
+        run.scenario.time_series.drain(..old_results);
+        #[cfg(feature = "infection-events")]
+        run.scenario.infection_events.clear();
+        let finished = run.time_step - 1;
+        let results: std::vec::Vec<_> = self
+            .replicates
+            .iter()
+            .filter_map(|run| run.scenario.time_series.last())
+            .filter(|tsr| tsr.time_step == finished)
+            .collect();
+        if results.len() == self.replicates.len() {
+            self.bands.push(Band::new(finished, &results));
+        }
+        let bands = self.bands[self.posted_bands..].to_vec();
+        self.posted_bands = self.bands.len();
+        Ok(Some(bands))
+    }
+
+    /// True when a stop condition held in the last time step; the final results keep the current time step
+    fn stopped(&self) -> bool {
+        self.scenario
+            .time_series
+            .last()
+            .map_or(false, |tsr| tsr.time_step == self.time_step)
+    }
+
+    /// Adds a comparison run with the parameters in the page; returns false when there are too many
+    fn compare(&mut self) -> Result<bool, AbmError> {
+        if self.comparisons.len() >= MAX_COMPARISONS {
+            return Ok(false);
+        }
+        let mut run = Model::new()?;
+        run.read_parameters()?;
+        run.scenario.id = self.comparisons.len() as u32 + 1;
+        self.comparisons.push(run);
+        Ok(true)
+    }
+
+    /// Results of the latest time step at or before `time_step`
+    #[cfg(feature = "landscape")]
+    fn results_at(&self, time_step: u32) -> Option<&TimeStepResults> {
+        self.scenario
+            .time_series
+            .iter()
+            .rev()
+            .find(|tsr| tsr.time_step <= time_step)
+    }
+
+    /// Collects the state that the main thread needs to draw the time steps since the previous frame
+    fn frame(&mut self, running: bool) -> Frame {
+        let restart = self.posted_time_steps == 0;
+        // Each result carries the changes of the landscape instead of a copy of it
+        #[cfg(feature = "landscape-graphics")]
+        let cell_series_changes = {
+            let series = &self.scenario.time_series;
+            (self.posted_time_steps..series.len())
+                .map(|idx| {
+                    let cell_health = &series[idx].cell_health;
+                    if cell_health.is_empty() {
+                        return None;
+                    }
+                    let previous = idx
+                        .checked_sub(1)
+                        .map(|idx| &series[idx].cell_health[..])
+                        .filter(|previous| !previous.is_empty());
+                    Some(cell_changes(previous, cell_health))
+                })
+                .collect()
+        };
+        let time_series = self.scenario.time_series[self.posted_time_steps..]
+            .iter_mut()
+            .map(|tsr| {
+                #[cfg(feature = "landscape-graphics")]
+                let cell_health = std::mem::take(&mut tsr.cell_health);
+                let posted = tsr.clone();
+                #[cfg(feature = "landscape-graphics")]
+                {
+                    tsr.cell_health = cell_health;
+                }
+                posted
+            })
+            .collect();
+        self.posted_time_steps = self.scenario.time_series.len();
+        #[cfg(feature = "landscape")]
+        let cell_changes = {
+            let previous = Some(&self.posted_cell_health[..]).filter(|_| !restart);
+            let changes = cell_changes(previous, &self.cell_health);
+            self.posted_cell_health.clone_from(&self.cell_health);
+            changes
+        };
+        let bands = self.bands[self.posted_bands..].to_vec();
+        self.posted_bands = self.bands.len();
+        #[cfg(feature = "infection-events")]
+        let infection_events = {
+            let events = &self.scenario.infection_events;
+            let new_events = events[self.posted_events..].to_vec();
+            self.posted_events = events.len();
+            new_events
+        };
+        // Only the agents and links in the network graph
+        #[cfg(feature = "net-graphics")]
+        let (graph_health, graph_links) = {
+            let graph_layout = &self.graph_layout;
+            let health = &self.health;
+            let graph_health = graph_layout
+                .keys()
+                .filter_map(|agent_key| Some((agent_key, *health.get(agent_key)?)))
+                .collect();
+            let graph_links = self
+                .links
+                .values()
+                .filter(|&&(key0, key1)| {
+                    graph_layout.contains_key(key0) && graph_layout.contains_key(key1)
+                })
+                .copied()
+                .collect();
+            (graph_health, graph_links)
+        };
+        Frame {
+            time_step: self.time_step,
+            running,
+            restart,
+            time_series,
+            #[cfg(feature = "net-graphics")]
+            histogram_degrees_set: self.scenario.histogram_degrees_set.clone(),
+            #[cfg(feature = "net-graphics")]
+            histogram_max_degree: self.scenario.histogram_max_degree,
+            #[cfg(feature = "net-graphics")]
+            histogram_height: self.scenario.histogram_height,
+            #[cfg(feature = "graphics")]
+            agent_time_series_height: self.scenario.agent_time_series_height,
+            #[cfg(feature = "landscape-graphics")]
+            cell_time_series_height: self.scenario.cell_time_series_height,
+            #[cfg(feature = "net-graphics")]
+            graph_health,
+            #[cfg(feature = "net-graphics")]
+            graph_links,
+            #[cfg(feature = "net-graphics")]
+            graph_layout: self.graph_layout.clone(),
+            #[cfg(feature = "landscape")]
+            cell_changes,
+            #[cfg(feature = "landscape-graphics")]
+            cell_series_changes,
+            #[cfg(feature = "landscape")]
+            cell_visits: self.cell_visits.clone(),
+            #[cfg(feature = "landscape")]
+            world_length: self.coord.width(),
+            #[cfg(not(feature = "landscape"))]
+            world_length: 0,
+            comparisons: self
+                .comparisons
+                .iter_mut()
+                .map(|run| {
+                    let running = !run.stopped();
+                    run.frame(running)
+                })
+                .collect(),
+            bands,
+            #[cfg(feature = "infection-events")]
+            infection_events,
+        }
+    }
+
+    /// Takes the state from a frame that the simulation worker posted
+    fn apply_frame(&mut self, frame: Frame) -> Result<(), AbmError> {
+        self.time_step = frame.time_step;
+        if frame.restart {
+            self.scenario.time_series.clear();
+            self.bands.clear();
+            #[cfg(feature = "infection-events")]
+            self.scenario.infection_events.clear();
+            #[cfg(feature = "graphics")]
+            {
+                self.view = None;
+            }
+        }
+        #[cfg(not(feature = "landscape-graphics"))]
+        self.scenario.time_series.extend(frame.time_series);
+        #[cfg(feature = "landscape-graphics")]
+        for (mut time_step_results, changes) in
+            frame.time_series.into_iter().zip(frame.cell_series_changes)
+        {
+            if let Some(changes) = changes {
+                time_step_results.cell_health = match self.scenario.time_series.last() {
+                    Some(previous) if !previous.cell_health.is_empty() => {
+                        previous.cell_health.clone()
+                    }
+                    _ => vec![Health::S; self.coord.size()],
+                };
+                apply_cell_changes(&mut time_step_results.cell_health, &changes);
+            }
+            self.scenario.time_series.push(time_step_results);
+            forget_old_landscape(&mut self.scenario.time_series);
+        }
+        self.bands.extend(frame.bands);
+        #[cfg(feature = "infection-events")]
+        self.scenario
+            .infection_events
+            .extend(frame.infection_events);
+        #[cfg(feature = "net-graphics")]
+        {
+            self.scenario.histogram_degrees_set = frame.histogram_degrees_set;
+            self.scenario.histogram_max_degree = frame.histogram_max_degree;
+            self.scenario.histogram_height = frame.histogram_height;
+            self.graph_health = frame.graph_health;
+            self.graph_links = frame.graph_links;
+            self.graph_layout = frame.graph_layout;
+        }
+        #[cfg(feature = "graphics")]
+        {
+            self.scenario.agent_time_series_height = frame.agent_time_series_height;
+        }
+        #[cfg(feature = "landscape-graphics")]
+        {
+            self.scenario.cell_time_series_height = frame.cell_time_series_height;
+        }
+        #[cfg(feature = "landscape")]
+        {
+            if frame.restart {
+                self.cell_health = vec![Health::S; self.coord.size()];
             }
+            apply_cell_changes(&mut self.cell_health, &frame.cell_changes);
+            self.cell_visits = frame.cell_visits;
+        }
+        if frame.restart || self.comparisons.len() != frame.comparisons.len() {
+            self.comparisons = frame
+                .comparisons
+                .iter()
+                .enumerate()
+                .map(|(idx, comparison)| {
+                    let mut run = Model::with_size(0, comparison.world_length)?;
+                    run.scenario.id = idx as u32 + 1;
+                    Ok(run)
+                })
+                .collect::<Result<_, AbmError>>()?;
         }
-        // Dynamics: infection spreads
+        self.comparisons
+            .iter_mut()
+            .zip(frame.comparisons)
+            .try_for_each(|(run, comparison)| run.apply_frame(comparison))
+    }
+
+    /// Infects or disinfects a landscape cell, in the visible frame and in the next time step.
+    /// Comparison and replicate runs get the same edit.
+    #[cfg(feature = "landscape")]
+    fn toggle_cell(&mut self, idx: usize) -> Option<Health> {
+        let h = match self.cell_health.get(idx)? {
+            Health::S => Health::I,
+            Health::I => Health::S,
+        };
+        self.set_cell(idx, h)?;
+        for run in self
+            .comparisons
+            .iter_mut()
+            .chain(self.replicates.iter_mut())
         {
-            // Model state: Agent health the next time step
-            let mut next_health = SecondaryMap::with_capacity(health.capacity());
-            #[cfg(feature = "net")]
-            links.values().for_each(|&(key0, key1)| {
-                let h0 = health[key0];
-                let h1 = health[key1];
-                if h0 == Health::S && h1 == Health::I && infection_distro.sample(&mut rng) {
-                    next_health.insert(key0, Health::I);
-                }
-                if h1 == Health::S && h0 == Health::I && infection_distro.sample(&mut rng) {
-                    next_health.insert(key1, Health::I);
-                }
-            });
-            if time_step == 0 {
-                health.iter().for_each(|(k, &h)| {
-                    if h == Health::S && initial_infection_distro.sample(&mut rng) {
-                        next_health.insert(k, Health::I);
-                    }
-                });
-            }
-            health.iter().for_each(|(k, &h)| {
-                // Choose a random cell to visit
-                #[cfg(feature = "landscape")]
-                let x = visit_distro.sample(&mut rng) as i32;
-                #[cfg(feature = "landscape")]
-                let y = visit_distro.sample(&mut rng) as i32;
+            run.set_cell(idx, h);
+        }
+        Some(h)
+    }
+
+    /// Sets the health of a landscape cell, in the visible frame and in the next time step
+    #[cfg(feature = "landscape")]
+    fn set_cell(&mut self, idx: usize, h: Health) -> Option<()> {
+        let time_step_results = self.scenario.time_series.last_mut()?;
+        if idx >= self.cell_health.len() {
+            return None;
+        }
+        // Replicate runs keep no landscape history
+        #[cfg(feature = "landscape-graphics")]
+        if let Some(cell) = time_step_results.cell_health.get_mut(idx) {
+            *cell = h;
+        }
+        #[cfg(not(feature = "landscape-graphics"))]
+        let _ = time_step_results;
+        self.cell_health[idx] = h;
+        self.next_cell_health[idx] = h;
+        Some(())
+    }
+
+    /// Without the landscape there are no cells
+    #[cfg(not(feature = "landscape"))]
+    fn toggle_cell(&mut self, _idx: usize) -> Option<Health> {
+        None
+    }
+
+    /// Draws the latest results on the canvas
+    #[cfg(feature = "graphics")]
+    fn render(&mut self) -> Result<(), AbmError> {
+        let canvas_backend = plotters_canvas::CanvasBackend::new("abm-canvas")
+            .ok_or_else(|| AbmError::Page("the canvas abm-canvas".to_string()))?;
+        self.geometry = self.draw(&canvas_backend.into_drawing_area(), js_dark_figures())?;
+        Ok(())
+    }
+
+    /// Without figures there is nothing to draw
+    #[cfg(not(feature = "graphics"))]
+    fn render(&self) -> Result<(), AbmError> {
+        Ok(())
+    }
+
+    /// Takes a parameter sweep that the simulation worker posted, for the sweep panel
+    #[cfg(feature = "graphics")]
+    fn show_sweep(&mut self, bytes: &[u8]) -> Result<(), AbmError> {
+        self.sweep = Some(Sweep::decode(bytes)?);
+        Ok(())
+    }
+
+    /// Without figures there is no sweep panel
+    #[cfg(not(feature = "graphics"))]
+    fn show_sweep(&mut self, _bytes: &[u8]) -> Result<(), AbmError> {
+        Ok(())
+    }
+
+    /// Draws the latest results as an SVG document with light figures in the selected palette, for publications
+    #[cfg(feature = "graphics")]
+    fn export_svg(&self, width: u32, height: u32) -> Result<String, AbmError> {
+        let mut svg = String::new();
+        {
+            let drawing_area =
+                SVGBackend::with_string(&mut svg, (width, height)).into_drawing_area();
+            self.draw(&drawing_area, false)?;
+            drawing_area.present()?;
+        }
+        Ok(svg)
+    }
+
+    /// Draws a crosshair and the values of the time step under a canvas pixel on the overlay canvas.
+    /// Returns false outside the time series charts.
+    #[cfg(feature = "graphics")]
+    fn hover(&self, x: i32, y: i32) -> Result<bool, AbmError> {
+        let spec = match self.geometry.time_chart(x, y) {
+            Some(spec) => spec,
+            None => return Ok(false),
+        };
+        // The time step nearest to the pointer, according to the chart's coordinate transform
+        let x_range = spec.get_x_range();
+        let time_step_results = match self
+            .scenario
+            .time_series
+            .iter()
+            .filter(|tsr| tsr.time_step >= x_range.start && tsr.time_step <= x_range.end)
+            .min_by_key(|tsr| (spec.translate(&(tsr.time_step, 0.0)).0 - x).abs())
+        {
+            Some(time_step_results) => time_step_results,
+            None => return Ok(true),
+        };
+        let overlay = plotters_canvas::CanvasBackend::new("abm-overlay")
+            .ok_or_else(|| AbmError::Page("the canvas abm-overlay".to_string()))?
+            .into_drawing_area();
+        let (width, height) = overlay.dim_in_pixel();
+        let scale = f64::min(width as f64 / 1920.0, height as f64 / 1080.0);
+        let text_size = (17.0 * scale).round().max(1.0);
+        let theme = Theme::new(&self.scenario, js_dark_figures());
+        let (color0, background_color) = (&theme.foreground, &theme.background);
+        let x_pixel = spec.translate(&(time_step_results.time_step, 0.0)).0;
+        let y_pixels = spec.get_y_axis_pixel_range();
+        overlay.draw(&PathElement::new(
+            vec![(x_pixel, y_pixels.start), (x_pixel, y_pixels.end)],
+            color0,
+        ))?;
+        #[allow(unused_mut)]
+        let mut readouts = vec![
+            format!("time_step {}", time_step_results.time_step),
+            format!("n {}", time_step_results.n),
+            format!("s {}", time_step_results.s),
+            format!("i {}", time_step_results.i),
+            format!(
+                "i/n {:.3}",
+                time_step_results.i as f64 / f64::max(time_step_results.n as f64, 1.0)
+            ),
+            format!("incidence {}", time_step_results.incidence),
+            format!("deaths {}", time_step_results.deaths),
+            format!("births {}", time_step_results.births),
+        ];
+        #[cfg(feature = "behavior")]
+        readouts.push(format!("a {}", time_step_results.a));
+        #[cfg(feature = "landscape-metrics")]
+        readouts.push(format!("c_i {}", time_step_results.c_i));
+        let line_height = (1.3 * text_size) as i32;
+        let top_left = (x_pixel + line_height / 2, y_pixels.start.min(y_pixels.end));
+        overlay.draw(&Rectangle::new(
+            [
+                top_left,
+                (
+                    top_left.0 + 8 * line_height,
+                    top_left.1 + readouts.len() as i32 * line_height,
+                ),
+            ],
+            background_color.mix(0.8).filled(),
+        ))?;
+        let text = ("Calibri", text_size).into_font().color(color0);
+        readouts.iter().enumerate().try_for_each(|(idx, readout)| {
+            overlay.draw_text(
+                readout,
+                &text,
+                (
+                    top_left.0 + line_height / 4,
+                    top_left.1 + idx as i32 * line_height,
+                ),
+            )
+        })?;
+        Ok(true)
+    }
+
+    /// Without figures there is nothing to read
+    #[cfg(not(feature = "graphics"))]
+    fn hover(&self, _x: i32, _y: i32) -> Result<bool, AbmError> {
+        Ok(false)
+    }
+
+    /// Zooms the time series charts into the time steps between two canvas pixels, or pans them by the distance between the pixels.
+    /// Zooming into a single pixel shows the whole run again. Returns false outside the time series charts.
+    #[cfg(feature = "graphics")]
+    fn zoom(&mut self, x0: i32, x1: i32, y: i32, pan: bool) -> bool {
+        let spec = match self.geometry.time_chart(x0, y) {
+            Some(spec) => spec,
+            None => return false,
+        };
+        if x0 == x1 && !pan {
+            self.time_window = None;
+            return true;
+        }
+        let x_range = spec.get_x_range();
+        let x_pixels = spec.get_x_axis_pixel_range();
+        let to_time = |pixel: i32| {
+            x_range.start as f64
+                + (pixel - x_pixels.start) as f64 * (x_range.end - x_range.start) as f64
+                    / (x_pixels.end - x_pixels.start).max(1) as f64
+        };
+        let (t0, t1) = if pan {
+            let shift = to_time(x0) - to_time(x1);
+            (x_range.start as f64 + shift, x_range.end as f64 + shift)
+        } else {
+            (to_time(x0.min(x1)), to_time(x0.max(x1)))
+        };
+        // Time starts at 0, and the window spans at least 2 time steps
+        let span = (t1 - t0).max(2.0);
+        let t0 = t0.max(0.0).round();
+        self.time_window = Some((t0 as u32, (t0 + span).round() as u32));
+        true
+    }
+
+    /// Without figures there is nothing to zoom
+    #[cfg(not(feature = "graphics"))]
+    fn zoom(&mut self, _x0: i32, _x1: i32, _y: i32, _pan: bool) -> bool {
+        false
+    }
+
+    /// Without a drawing of the landscape there are no cells to inspect
+    #[cfg(not(feature = "landscape-graphics"))]
+    fn inspect_cell(&mut self, _x: i32, _y: i32, _toggle: bool) -> Option<String> {
+        None
+    }
+
+    /// Without figures there is nothing to export
+    #[cfg(not(feature = "graphics"))]
+    fn export_svg(&self, _width: u32, _height: u32) -> Result<String, AbmError> {
+        Ok(String::new())
+    }
+
+    /// Infection events as CSV, one row per infection.
+    /// Agent identifiers are unique during the run; `secondary_cases` only counts infections across the network.
+    #[cfg(feature = "infection-events")]
+    fn export_events(&self) -> String {
+        let events = &self.scenario.infection_events;
+        let tree = TransmissionTree::new(events);
+        let mut csv = "time_step,infectee,route,infector,cell,secondary_cases\n".to_string();
+        for (event, cases) in events.iter().zip(tree.secondary_cases) {
+            let (infector, cell) = match event.source {
+                Source::Seed => (String::new(), String::new()),
+                #[cfg(feature = "net")]
+                Source::Neighbor(infector) => (agent_id(infector).to_string(), String::new()),
                 #[cfg(feature = "landscape")]
-                let idx = coord.index(x, y);
-                match h {
-                    Health::S => {
-                        #[cfg(feature = "landscape")]
-                        {
-                            if cell_health[idx] == Health::I && infection_distro.sample(&mut rng) {
-                                // Cell infects agent
-                                next_health.insert(k, Health::I);
-                            }
-                        }
-                    }
-                    Health::I => {
-                        #[cfg(feature = "landscape")]
-                        {
-                            if cell_health[idx] == Health::S && infection_distro.sample(&mut rng) {
-                                // Agent infects cell
-                                next_cell_health[idx] = Health::I;
-                            }
-                        }
-                        if recovery_distro.sample(&mut rng) {
-                            next_health.insert(k, Health::S);
-                        }
-                    }
-                };
-            });
-            // Dynamics: Disease spreads across cells and infectious cells recover
-            #[cfg(feature = "landscape")]
-            coord.for_each8(
-                |this_cell_index, neighbors| match cell_health[this_cell_index] {
-                    Health::S => {
-                        for neighbor_index in neighbors {
-                            if cell_health[*neighbor_index] == Health::I
-                                && infection_distro.sample(&mut rng)
-                            {
-                                next_cell_health[this_cell_index] = Health::I;
-                                break;
-                            }
-                        }
-                    }
-                    Health::I => {
-                        if recovery_distro.sample(&mut rng) {
-                            next_cell_health[this_cell_index] = Health::S;
-                        }
-                    }
-                },
+                Source::Cell(idx) => (String::new(), idx.to_string()),
+            };
+            csv += &format!(
+                "{},{},{},{},{},{}\n",
+                event.time_step,
+                agent_id(event.infectee),
+                event.source.route(),
+                infector,
+                cell,
+                cases
             );
-            // Dynamics: After spreading the infection, some infectious agents die
-            health.retain(|_agent_key, h| match h {
-                Health::S => true,
-                Health::I => survival_distro.sample(&mut rng),
-            });
-            // Dynamics: Remaining agents update in parallel
-            next_health.iter().for_each(|(k, &next_h)| {
-                if let Some(h) = health.get_mut(k) {
-                    *h = next_h;
-                }
-            });
-            // Dynamics: cells update in parallel
-            #[cfg(feature = "landscape")]
-            {
-                cell_health = next_cell_health.clone();
-            }
         }
-        // Dynamics: Prune network
-        #[cfg(feature = "net")]
-        links.retain(|_link_key, (key0, key1)| {
-            health.contains_key(*key0) && health.contains_key(*key1)
-        });
-        // Dynamics: New agents emerge
-        let nb = health
-            .values()
-            .filter(|&&h| h == Health::S && birth_distro.sample(&mut rng))
-            .count();
-        for _ in 0..nb {
-            health.insert(Health::S);
+        csv
+    }
+
+    /// Without infection events there is nothing to export
+    #[cfg(not(feature = "infection-events"))]
+    fn export_events(&self) -> String {
+        js_message("This build does not record infection events.");
+        String::new()
+    }
+
+    /// Results of the time step shown in the figures
+    #[cfg(feature = "graphics")]
+    fn shown_results(&self) -> Option<&TimeStepResults> {
+        match self.view {
+            Some(time_step) => self
+                .scenario
+                .time_series
+                .iter()
+                .rev()
+                .find(|tsr| tsr.time_step <= time_step),
+            None => self.scenario.time_series.last(),
         }
-        // end-similar-code 3
-        let time_series_len = core::cmp::max(time_step, 10);
+    }
+
+    /// Shows an earlier time step in the figures; the latest time step or later follows the simulation
+    #[cfg(feature = "graphics")]
+    fn set_view(&mut self, time_step: u32) {
+        let latest = self
+            .scenario
+            .time_series
+            .last()
+            .map_or(0, |tsr| tsr.time_step);
+        self.view = if time_step < latest {
+            Some(time_step)
+        } else {
+            None
+        };
+    }
+
+    /// Without figures there is nothing to show
+    #[cfg(not(feature = "graphics"))]
+    fn set_view(&mut self, _time_step: u32) {}
+
+    /// Draws the latest results on any plotters backend
+    #[cfg(feature = "graphics")]
+    fn draw<DB: DrawingBackend>(
+        &self,
+        drawing_area: &DrawingArea<DB, plotters::coord::Shift>,
+        dark_figures: bool,
+    ) -> Result<Geometry, AbmError>
+    where
+        DB::ErrorType: 'static,
+    {
+        let time_step_results = match self.shown_results() {
+            Some(time_step_results) => time_step_results,
+            None => return Ok(Geometry::default()),
+        };
+        let scenario = &self.scenario;
+        #[cfg(feature = "net-graphics")]
+        let histogram_mode = scenario.histogram_mode;
+        #[cfg(feature = "net-graphics")]
+        let compress_histogram = histogram_mode == HistogramMode::Compressed;
+        #[cfg(feature = "landscape")]
+        let coord = &self.coord;
+        let time_series_len = core::cmp::max(time_step_results.time_step, 10);
+        let (time_min, time_max) = self.time_window.unwrap_or((0, time_series_len));
+        let in_window =
+            |tsr: &&TimeStepResults| tsr.time_step >= time_min && tsr.time_step <= time_max;
         // begin-similar-code 4
         #[cfg(feature = "graphics")]
         let mut agent_time_series_height = 0;
@@ -464,49 +3753,52 @@ pub fn rs_deploy_scenario() {
         #[cfg(feature = "net-graphics")]
         let mut histogram_height = 0;
         // end-similar-code 4
-        // begin-similar-code 5
-        #[cfg(feature = "graphics")]
-        {
-            #[cfg(feature = "net-graphics")]
+        // Comparison runs share the axes with this one
+        let runs =
+            std::iter::once(scenario).chain(self.comparisons.iter().map(|run| &run.scenario));
+        for scenario in runs {
+            // begin-similar-code 5
+            #[cfg(feature = "graphics")]
             {
-                if compress_histogram {
-                    for degree in scenario.histogram_degrees_set.iter() {
-                        histogram_degrees_set.insert(degree);
+                #[cfg(feature = "net-graphics")]
+                {
+                    if compress_histogram {
+                        for degree in scenario.histogram_degrees_set.iter() {
+                            histogram_degrees_set.insert(degree);
+                        }
+                    } else if histogram_max_degree < scenario.histogram_max_degree {
+                        histogram_max_degree = scenario.histogram_max_degree;
+                    }
+                    if histogram_height < scenario.histogram_height {
+                        histogram_height = scenario.histogram_height;
                     }
-                } else if histogram_max_degree < scenario.histogram_max_degree {
-                    histogram_max_degree = scenario.histogram_max_degree;
                 }
-                if histogram_height < scenario.histogram_height {
-                    histogram_height = scenario.histogram_height;
+                if agent_time_series_height < scenario.agent_time_series_height {
+                    agent_time_series_height = scenario.agent_time_series_height;
                 }
             }
-            if agent_time_series_height < scenario.agent_time_series_height {
-                agent_time_series_height = scenario.agent_time_series_height;
+            #[cfg(feature = "landscape-graphics")]
+            {
+                if cell_time_series_height < scenario.cell_time_series_height {
+                    cell_time_series_height = scenario.cell_time_series_height;
+                }
             }
+            // end-similar-code 5
         }
-        #[cfg(feature = "landscape-graphics")]
-        {
-            if cell_time_series_height < scenario.cell_time_series_height {
-                cell_time_series_height = scenario.cell_time_series_height;
-            }
+        if agent_time_series_height == 0 {
+            agent_time_series_height = 10;
         }
-        // end-similar-code 5
         #[cfg(feature = "landscape-graphics")]
         if cell_time_series_height == 0 {
             cell_time_series_height = 10;
         }
+        // Sizes are designed for a 1920x1080 canvas and scale with the actual canvas
+        let (width, height) = drawing_area.dim_in_pixel();
+        let scale = f64::min(width as f64 / 1920.0, height as f64 / 1080.0);
+        let scaled = |size: u32| ((size as f64 * scale).round() as u32).max(1);
+        let at = |x: i32, y: i32| ((x as f64 * scale) as i32, (y as f64 * scale) as i32);
         // begin-similar-code 6
         #[cfg(feature = "net-graphics")]
-        {
-            if compress_histogram {
-                assert!(!histogram_degrees_set.is_empty());
-            }
-            assert!(histogram_height > 0);
-        }
-        assert!(agent_time_series_height > 0);
-        #[cfg(feature = "landscape-graphics")]
-        assert!(cell_time_series_height > 0);
-        #[cfg(feature = "net-graphics")]
         {
             // A little extra space in the chart:
             histogram_height += 1;
@@ -519,39 +3811,33 @@ pub fn rs_deploy_scenario() {
         }
         #[cfg(feature = "net-graphics")]
         let x_degree: std::vec::Vec<_> = histogram_degrees_set.iter().enumerate().collect();
-        let figure_margin = 5;
+        let figure_margin = scaled(5);
         #[cfg(feature = "net-graphics")]
-        let bar_margin = 3;
-        let thick_stroke = 4;
-        let text_size0 = 30;
-        let text_size1 = 17;
-        let x_label_area_size = 40;
+        let bar_margin = scaled(3);
+        let thick_stroke = scaled(4);
+        let comparison_stroke = scaled(2);
+        let text_size0 = scaled(30);
+        let text_size1 = scaled(17);
+        let x_label_area_size = scaled(40);
         #[cfg(feature = "net-graphics")]
-        let x_label_offset = 1;
-        let y_label_area_size = 60;
+        let x_label_offset = scaled(1);
+        let y_label_area_size = scaled(60);
         // end-similar-code 6
-        let dark_figures = js_dark_figures();
-        let canvas_backend =
-            plotters_canvas::CanvasBackend::new("abm-canvas").expect("cannot find canvas");
-        use plotters::drawing::IntoDrawingArea;
-        let drawing_area = canvas_backend.into_drawing_area();
         // begin-similar-code 7
-        let background_color = if dark_figures { &BLACK } else { &WHITE };
+        let theme = Theme::new(scenario, dark_figures);
+        let background_color = &theme.background;
         let _transparent_color = background_color.mix(0.);
-        let color0 = if dark_figures { &WHITE } else { &BLACK };
+        let color0 = &theme.foreground;
         let color01 = color0.mix(0.1);
         let color02 = color0.mix(0.2);
-        let color1 = if dark_figures {
-            &plotters::style::RGBColor(255, 192, 0)
-        } else {
-            &RED
-        };
-        let color2 = &plotters::style::RGBColor(0, 176, 80);
-        let color3 = &plotters::style::RGBColor(32, 56, 100);
+        let color1 = &theme.accent;
+        let color2 = &theme.susceptible;
+        let color3 = &theme.infected;
+        let color4 = &theme.incidence;
+        let color5 = &theme.prevalence;
+        let color6 = &theme.births;
         let color_s = color2;
         let color_i = color3;
-        let color0t = color0.stroke_width(thick_stroke);
-        let _color1t = color1.stroke_width(thick_stroke);
         let color2t = color2.stroke_width(thick_stroke);
         let color3t = color3.stroke_width(thick_stroke);
         let _color_st = color2t;
@@ -564,85 +3850,200 @@ pub fn rs_deploy_scenario() {
         let _fill3 = color3.filled();
         let text0 = ("Calibri", text_size0).into_font().color(color0);
         let text1 = ("Calibri", text_size1).into_font().color(color0);
-        drawing_area.fill(background_color).unwrap();
-        let (left_area, right_area) = drawing_area.split_horizontally(1920 - 1080);
-        let left_panels = left_area.split_evenly((4, 1));
-        left_panels[0]
-            .draw_text(
+        // Replicate runs up to the time step shown, as a faint band and a thin median line
+        let bands: std::vec::Vec<&Band> = self
+            .bands
+            .iter()
+            .filter(|band| {
+                band.time_step >= time_min
+                    && band.time_step <= time_max
+                    && band.time_step <= time_step_results.time_step
+            })
+            .collect();
+        let band_shapes = |quantiles: fn(&Band) -> [f64; 3], color: RGBAColor| {
+            let area: std::vec::Vec<(u32, f64)> = bands
+                .iter()
+                .map(|band| (band.time_step, quantiles(band)[2]))
+                .chain(
+                    bands
+                        .iter()
+                        .rev()
+                        .map(|band| (band.time_step, quantiles(band)[0])),
+                )
+                .collect();
+            let median = bands
+                .iter()
+                .map(|band| (band.time_step, quantiles(band)[1]))
+                .collect::<std::vec::Vec<_>>();
+            (
+                Polygon::new(area, color.mix(0.2).filled()),
+                LineSeries::new(median, color.stroke_width(comparison_stroke)),
+            )
+        };
+        drawing_area.fill(background_color)?;
+        let mut geometry = Geometry::default();
+        // The side column is square; the charts take the rest of the canvas
+        let dashboard = &scenario.dashboard;
+        let panels = if dashboard.side.is_empty() {
+            dashboard.split_column(drawing_area, &dashboard.charts)
+        } else {
+            let (chart_area, side_area) = if width >= height {
+                drawing_area.split_horizontally(width - u32::min(height, width * 9 / 16))
+            } else {
+                drawing_area.split_vertically(height - u32::min(width, height * 9 / 16))
+            };
+            let mut panels = dashboard.split_column(&chart_area, &dashboard.charts);
+            panels.extend(dashboard.split_column(&side_area, &dashboard.side));
+            panels
+        };
+        let panel_area = |panel: Panel| {
+            panels
+                .iter()
+                .find(|(p, _area)| *p == panel)
+                .map(|(_p, area)| area)
+        };
+        if let Some(header) = panel_area(Panel::Header) {
+            header.draw_text(
                 &format!("infection_probability = {}", scenario.infection_probability),
                 &text0,
-                (50, 10),
-            )
-            .unwrap();
-        #[cfg(feature = "net-graphics")]
-        {
-            left_panels[0]
-                .draw_text(
+                at(50, 10),
+            )?;
+            #[cfg(feature = "net-graphics")]
+            {
+                header.draw_text(
                     &format!("d_s Max degree of susceptibles: {}", time_step_results.d_s),
                     &text0,
-                    (50, 100),
-                )
-                .unwrap();
-            left_panels[0]
-                .draw_text(
+                    at(50, 100),
+                )?;
+                header.draw_text(
                     &format!(
                         "d_i Max degree of infectious agents: {}",
                         time_step_results.d_i
                     ),
                     &text0,
-                    (50, 140),
-                )
-                .unwrap();
-        }
-        left_panels[0]
-            .draw_text(
+                    at(50, 140),
+                )?;
+            }
+            #[cfg(feature = "contact-tracing")]
+            {
+                header.draw_text(
+                    &format!(
+                        "t_d Detected: {}   t_q Quarantined: {}   t_m Missed: {}",
+                        time_step_results.t_d, time_step_results.t_q, time_step_results.t_m
+                    ),
+                    &text0,
+                    at(50, 55),
+                )?;
+            }
+            header.draw_text(
                 &format!("time: {}", time_step_results.time_step),
                 &text0,
-                (500, 10),
-            )
-            .unwrap();
+                at(500, 10),
+            )?;
+            // Summary up to the time step shown
+            let shown = scenario
+                .time_series
+                .partition_point(|tsr| tsr.time_step <= time_step_results.time_step);
+            if let Some(summary) = Summary::of(&scenario.time_series[..shown]) {
+                for (line, y) in summary.lines().iter().zip([185, 210]) {
+                    header.draw_text(line, &text1, at(50, y))?;
+                }
+            }
+        }
         #[cfg(feature = "net-graphics")]
-        {
-            let x_range = if compress_histogram {
-                0..x_degree.len() as i32
-            } else {
-                0..histogram_max_degree
+        if let Some(area) = panel_area(Panel::Degrees) {
+            let exponent = power_law_exponent(&time_step_results.degree_histogram);
+            let caption = match exponent {
+                Some(alpha) => format!("Network degree of agents (exponent {:.2})", alpha),
+                None => "Network degree of agents".to_string(),
             };
-            let mut chart = ChartBuilder::on(&left_panels[1])
-                .x_label_area_size(x_label_area_size)
-                .y_label_area_size(y_label_area_size)
-                .margin(figure_margin)
-                .caption("Network degree of agents", text0.clone())
-                .build_cartesian_2d(x_range, 0..histogram_height)
-                .unwrap();
-            chart
-                .configure_mesh()
-                .light_line_style(&color01)
-                .bold_line_style(&color02)
-                .y_desc("Number of agents")
-                .x_desc(if compress_histogram {
-                    "Network degree (removing zeroes)"
+            if histogram_mode == HistogramMode::LogLog {
+                let ccdf = degree_ccdf(&time_step_results.degree_histogram);
+                let (k_max, p_min) = ccdf.last().map_or((1.0, 1.0), |&(k, p)| (k as f64, p));
+                let mut chart = ChartBuilder::on(area)
+                    .x_label_area_size(x_label_area_size)
+                    .y_label_area_size(y_label_area_size)
+                    .margin(figure_margin)
+                    .caption(&caption, text0.clone())
+                    .build_cartesian_2d(
+                        (1.0..k_max * 2.0).log_scale(),
+                        (p_min / 2.0..1.0).log_scale(),
+                    )?;
+                chart
+                    .configure_mesh()
+                    .light_line_style(color01)
+                    .bold_line_style(color02)
+                    .y_desc("Fraction of agents with degree k or more")
+                    .x_desc("Network degree k (removing zeroes)")
+                    .axis_style(color0)
+                    .axis_desc_style(text1.clone())
+                    .label_style(text1.clone())
+                    .draw()?;
+                let point_size = scaled(4);
+                chart
+                    .draw_series(
+                        ccdf.iter()
+                            .map(|&(k, p)| Circle::new((k as f64, p), point_size, color0.filled())),
+                    )?
+                    .label("Agents")
+                    .legend(move |(x, y)| Circle::new((x + 10, y), point_size, color0.filled()));
+                if let Some(alpha) = exponent {
+                    // Complementary cumulative distribution of the fitted power law
+                    let fit = (0..=100).map(|i| {
+                        let k = k_max.powf(i as f64 / 100.0);
+                        (k, ((k - 0.5) / 0.5).powf(1.0 - alpha).min(1.0))
+                    });
+                    let fit_style = color5.stroke_width(thick_stroke);
+                    chart
+                        .draw_series(LineSeries::new(fit, fit_style))?
+                        .label(format!("Power law, exponent {:.2}", alpha))
+                        .legend(move |(x, y)| {
+                            PathElement::new(vec![(x, y), (x + 20, y)], fit_style)
+                        });
+                }
+                chart
+                    .configure_series_labels()
+                    .label_font(text1.clone())
+                    .border_style(color0)
+                    .draw()?;
+            } else {
+                let x_range = if compress_histogram {
+                    0..x_degree.len() as i32
                 } else {
-                    "Network degree"
-                })
-                .axis_style(color0)
-                .axis_desc_style(text1.clone())
-                .label_style(text1.clone())
-                .x_label_offset(x_label_offset)
-                .x_label_formatter(&|x_position| {
-                    if compress_histogram {
-                        match x_degree.get(*x_position as usize) {
-                            Some(x_deg) => format!("{}", x_deg.1),
-                            None => format!(""),
-                        }
+                    0..histogram_max_degree
+                };
+                let mut chart = ChartBuilder::on(area)
+                    .x_label_area_size(x_label_area_size)
+                    .y_label_area_size(y_label_area_size)
+                    .margin(figure_margin)
+                    .caption(&caption, text0.clone())
+                    .build_cartesian_2d(x_range, 0..histogram_height)?;
+                chart
+                    .configure_mesh()
+                    .light_line_style(&color01)
+                    .bold_line_style(&color02)
+                    .y_desc("Number of agents")
+                    .x_desc(if compress_histogram {
+                        "Network degree (removing zeroes)"
                     } else {
-                        format!("{}", x_position)
-                    }
-                })
-                .draw()
-                .unwrap();
-            chart
-                .draw_series(
+                        "Network degree"
+                    })
+                    .axis_style(color0)
+                    .axis_desc_style(text1.clone())
+                    .label_style(text1.clone())
+                    .x_label_offset(x_label_offset)
+                    .x_label_formatter(&|x_position| {
+                        if compress_histogram {
+                            match x_degree.get(*x_position as usize) {
+                                Some(x_deg) => format!("{}", x_deg.1),
+                                None => format!(""),
+                            }
+                        } else {
+                            format!("{}", x_position)
+                        }
+                    })
+                    .draw()?;
+                chart.draw_series(
                     Histogram::vertical(&chart)
                         .style(background_color.filled())
                         .margin(bar_margin)
@@ -655,9 +4056,8 @@ pub fn rs_deploy_scenario() {
                                         if compress_histogram {
                                             x_degree
                                                 .iter()
-                                                .find(|&(_, &deg)| deg == degree)
-                                                .unwrap()
-                                                .0
+                                                .position(|&(_, &deg)| deg == degree)
+                                                .unwrap_or_default()
                                                 as i32
                                         } else {
                                             *degree
@@ -666,10 +4066,8 @@ pub fn rs_deploy_scenario() {
                                     )
                                 }),
                         ),
-                )
-                .unwrap();
-            chart
-                .draw_series(
+                )?;
+                chart.draw_series(
                     Histogram::vertical(&chart)
                         .style(color0)
                         .margin(bar_margin)
@@ -682,9 +4080,8 @@ pub fn rs_deploy_scenario() {
                                         if compress_histogram {
                                             x_degree
                                                 .iter()
-                                                .find(|&(_, &deg)| deg == degree)
-                                                .unwrap()
-                                                .0
+                                                .position(|&(_, &deg)| deg == degree)
+                                                .unwrap_or_default()
                                                 as i32
                                         } else {
                                             *degree
@@ -693,151 +4090,1482 @@ pub fn rs_deploy_scenario() {
                                     )
                                 }),
                         ),
-                )
-                .unwrap();
+                )?;
+            }
         }
-        {
-            let mut chart = ChartBuilder::on(&left_panels[2])
+        if let Some(area) = panel_area(Panel::Agents) {
+            let proportional = scenario.proportional;
+            let y_max = if proportional {
+                1.0
+            } else {
+                agent_time_series_height as f64
+            };
+            // In absolute mode, prevalence uses the secondary axis
+            let secondary = !proportional && scenario.agent_series.contains(&Series::Prevalence);
+            let mut chart = ChartBuilder::on(area)
                 .x_label_area_size(x_label_area_size)
                 .y_label_area_size(y_label_area_size)
+                .right_y_label_area_size(if secondary { y_label_area_size } else { 0 })
                 .margin(figure_margin)
                 .caption("Populations of agents", text0.clone())
-                .build_cartesian_2d(0..(time_series_len as u32), 0..agent_time_series_height)
-                .unwrap();
+                .build_cartesian_2d(time_min..time_max, 0.0..y_max)?
+                .set_secondary_coord(time_min..time_max, 0.0..1.0);
             chart
                 .configure_mesh()
                 .light_line_style(&color01)
                 .bold_line_style(&color02)
-                .y_desc("Number of agents")
+                .y_desc(if proportional {
+                    "Proportion of agents"
+                } else {
+                    "Number of agents"
+                })
                 .x_desc("Time")
                 .axis_style(color0)
                 .axis_desc_style(text1.clone())
                 .label_style(text1.clone())
-                .draw()
-                .unwrap();
-            chart
-                .draw_series(LineSeries::new(
-                    scenario
-                        .time_series
-                        .iter()
-                        .skip_while(|tsr| tsr.time_step < time_step_results.time_step)
-                        .map(|time_step_results| {
-                            (time_step_results.time_step, time_step_results.n)
-                        }),
-                    color0,
-                ))
-                .unwrap();
-            chart
-                .draw_series(LineSeries::new(
-                    scenario
-                        .time_series
-                        .iter()
-                        .take_while(|tsr| tsr.time_step <= time_step_results.time_step)
-                        .map(|time_step_results| {
-                            (time_step_results.time_step, time_step_results.n)
-                        }),
-                    color0t.clone(),
-                ))
-                .unwrap()
-                .label("n Number of agents")
-                .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color0t.clone()));
-            chart
-                .draw_series(LineSeries::new(
-                    scenario
-                        .time_series
-                        .iter()
-                        .skip_while(|tsr| tsr.time_step < time_step_results.time_step)
-                        .map(|time_step_results| {
-                            (time_step_results.time_step, time_step_results.i)
-                        }),
-                    color_i,
-                ))
-                .unwrap();
-            chart
-                .draw_series(LineSeries::new(
-                    scenario
+                .draw()?;
+            if secondary {
+                chart
+                    .configure_secondary_axes()
+                    .y_desc("Prevalence")
+                    .axis_style(color0)
+                    .axis_desc_style(text1.clone())
+                    .label_style(text1.clone())
+                    .draw()?;
+            }
+            geometry.time_charts.push(chart.as_coord_spec().clone());
+            let series_color = |series: Series| match series {
+                Series::N => color0.to_rgba(),
+                Series::S => color_s.to_rgba(),
+                Series::I => color_i.to_rgba(),
+                #[cfg(feature = "behavior")]
+                Series::A => color1.to_rgba(),
+                Series::Prevalence => color5.to_rgba(),
+                Series::Incidence => color4.to_rgba(),
+                Series::Deaths => color0.mix(0.5),
+                Series::Births => color6.to_rgba(),
+            };
+            let value = |series: Series, tsr: &TimeStepResults| {
+                if proportional && series != Series::Prevalence {
+                    series.value(tsr) / f64::max(tsr.n as f64, 1.0)
+                } else {
+                    series.value(tsr)
+                }
+            };
+            // Replicate runs: median and 5-95% band, under the live run
+            if !bands.is_empty() {
+                for &series in scenario.agent_series.iter() {
+                    let quantiles: fn(&Band) -> [f64; 3] = match (series, proportional) {
+                        (Series::N, false) => |band| band.n,
+                        (Series::I, false) => |band| band.i,
+                        (Series::I, true) | (Series::Prevalence, _) => |band| band.prevalence,
+                        _ => continue,
+                    };
+                    let (area, median) = band_shapes(quantiles, series_color(series));
+                    if series == Series::Prevalence && !proportional {
+                        chart.draw_secondary_series(std::iter::once(area))?;
+                        chart.draw_secondary_series(median)?;
+                    } else {
+                        chart.draw_series(std::iter::once(area))?;
+                        chart.draw_series(median)?;
+                    }
+                }
+                chart
+                    .draw_series(std::iter::empty::<Polygon<(u32, f64)>>())?
+                    .label(format!(
+                        "Median and 5-95% of {} replicates",
+                        bands.last().map_or(0, |band| band.replicates)
+                    ))
+                    .legend(move |(x, y)| {
+                        Rectangle::new([(x, y - 5), (x + 20, y + 5)], color02.filled())
+                    });
+            }
+            for &series in scenario.agent_series.iter() {
+                let style = ShapeStyle::from(&series_color(series));
+                let thick_style = style.stroke_width(thick_stroke);
+                // Thin lines show the time steps after the current one
+                let future = scenario
+                    .time_series
+                    .iter()
+                    .filter(in_window)
+                    .skip_while(|tsr| tsr.time_step < time_step_results.time_step)
+                    .map(|tsr| (tsr.time_step, value(series, tsr)));
+                let past = scenario
+                    .time_series
+                    .iter()
+                    .filter(in_window)
+                    .take_while(|tsr| tsr.time_step <= time_step_results.time_step)
+                    .map(|tsr| (tsr.time_step, value(series, tsr)));
+                let legend = move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], thick_style);
+                if series == Series::Prevalence && !proportional {
+                    chart.draw_secondary_series(LineSeries::new(future, style))?;
+                    chart
+                        .draw_secondary_series(LineSeries::new(past, thick_style))?
+                        .label(series.label())
+                        .legend(legend);
+                } else {
+                    chart.draw_series(LineSeries::new(future, style))?;
+                    chart
+                        .draw_series(LineSeries::new(past, thick_style))?
+                        .label(series.label())
+                        .legend(legend);
+                }
+            }
+            // Comparison runs: faint lines, numbered at the time step shown
+            for run in self.comparisons.iter() {
+                let number = format!("{}", run.scenario.id + 1);
+                for &series in scenario.agent_series.iter() {
+                    let style = series_color(series)
+                        .mix(0.6)
+                        .stroke_width(comparison_stroke);
+                    let past: std::vec::Vec<_> = run
+                        .scenario
                         .time_series
                         .iter()
+                        .filter(in_window)
                         .take_while(|tsr| tsr.time_step <= time_step_results.time_step)
-                        .map(|time_step_results| {
-                            (time_step_results.time_step, time_step_results.i)
-                        }),
-                    color_it.clone(),
-                ))
-                .unwrap()
-                .label("i Infected agents")
-                .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color_it.clone()));
+                        .map(|tsr| (tsr.time_step, value(series, tsr)))
+                        .collect();
+                    let label = past
+                        .last()
+                        .map(|&point| Text::new(number.clone(), point, text1.clone()));
+                    if series == Series::Prevalence && !proportional {
+                        chart.draw_secondary_series(LineSeries::new(past, style))?;
+                        chart.draw_secondary_series(label)?;
+                    } else {
+                        chart.draw_series(LineSeries::new(past, style))?;
+                        chart.draw_series(label)?;
+                    }
+                }
+                let legend_style = color0.mix(0.6).stroke_width(comparison_stroke);
+                chart
+                    .draw_series(LineSeries::new(
+                        std::iter::empty::<(u32, f64)>(),
+                        legend_style,
+                    ))?
+                    .label(format!("{}, faint lines", run.scenario.label()))
+                    .legend(move |(x, y)| {
+                        PathElement::new(vec![(x, y), (x + 20, y)], legend_style)
+                    });
+            }
+            if scenario.demography == Demography::Logistic && !proportional {
+                let carrying_capacity = scenario.carrying_capacity as f64;
+                chart
+                    .draw_series(LineSeries::new(
+                        [(time_min, carrying_capacity), (time_max, carrying_capacity)],
+                        color02,
+                    ))?
+                    .label("K Carrying capacity")
+                    .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color02));
+            }
             chart
                 .configure_series_labels()
                 .label_font(text1.clone())
                 .border_style(color0)
-                .draw()
-                .unwrap();
+                .draw()?;
+        }
+        #[cfg(feature = "landscape")]
+        if let Some(area) = panel_area(Panel::Cells) {
+            let mut chart = ChartBuilder::on(area)
+                .x_label_area_size(x_label_area_size)
+                .y_label_area_size(y_label_area_size)
+                .margin(figure_margin)
+                .caption("Infection of cells", text0.clone())
+                .build_cartesian_2d(time_min..time_max, 0.0..cell_time_series_height as f64)?;
+            chart
+                .configure_mesh()
+                .light_line_style(&color01)
+                .bold_line_style(&color02)
+                .y_desc("Number of infected cells")
+                .x_desc("Time")
+                .axis_style(color0)
+                .axis_desc_style(text1.clone())
+                .label_style(text1.clone())
+                .draw()?;
+            geometry.time_charts.push(chart.as_coord_spec().clone());
+            if !bands.is_empty() {
+                let (area, median) = band_shapes(|band| band.c_i, color_i.to_rgba());
+                chart.draw_series(std::iter::once(area))?;
+                chart.draw_series(median)?;
+            }
+            chart.draw_series(LineSeries::new(
+                scenario
+                    .time_series
+                    .iter()
+                    .filter(in_window)
+                    .skip_while(|tsr| tsr.time_step < time_step_results.time_step)
+                    .map(|tsr| (tsr.time_step, tsr.c_i as f64)),
+                color_i,
+            ))?;
+            chart.draw_series(LineSeries::new(
+                scenario
+                    .time_series
+                    .iter()
+                    .filter(in_window)
+                    .take_while(|tsr| tsr.time_step <= time_step_results.time_step)
+                    .map(|tsr| (tsr.time_step, tsr.c_i as f64)),
+                color_it,
+            ))?;
+            // Comparison runs: faint lines, numbered at the time step shown
+            let style = color_i.mix(0.6).stroke_width(comparison_stroke);
+            for run in self.comparisons.iter() {
+                let past: std::vec::Vec<_> = run
+                    .scenario
+                    .time_series
+                    .iter()
+                    .filter(in_window)
+                    .take_while(|tsr| tsr.time_step <= time_step_results.time_step)
+                    .map(|tsr| (tsr.time_step, tsr.c_i as f64))
+                    .collect();
+                let label = past.last().map(|&point| {
+                    Text::new(format!("{}", run.scenario.id + 1), point, text1.clone())
+                });
+                chart.draw_series(LineSeries::new(past, style))?;
+                chart.draw_series(label)?;
+            }
+        }
+        #[cfg(feature = "net-graphics")]
+        if let Some(area) = panel_area(Panel::Network) {
+            let graph_area = area.margin(scaled(10), scaled(10), scaled(10), scaled(10));
+            let (width, height) = graph_area.dim_in_pixel();
+            let node_size = scaled(4) as i32;
+            let to_pixels = |(x, y): (f64, f64)| {
+                (
+                    node_size + (x * (width as i32 - 2 * node_size) as f64) as i32,
+                    node_size + (y * (height as i32 - 2 * node_size) as f64) as i32,
+                )
+            };
+            self.graph_links.iter().try_for_each(|&(key0, key1)| {
+                match (self.graph_layout.get(key0), self.graph_layout.get(key1)) {
+                    (Some(&p0), Some(&p1)) => graph_area.draw(&PathElement::new(
+                        vec![to_pixels(p0), to_pixels(p1)],
+                        color02,
+                    )),
+                    _ => Ok(()),
+                }
+            })?;
+            self.graph_layout.iter().try_for_each(|(agent_key, &p)| {
+                match self.graph_health.get(agent_key) {
+                    Some(Health::S) => {
+                        graph_area.draw(&Circle::new(to_pixels(p), node_size, color_s.filled()))
+                    }
+                    Some(Health::I) => {
+                        graph_area.draw(&Circle::new(to_pixels(p), node_size, color_i.filled()))
+                    }
+                    None => Ok(()),
+                }
+            })?;
+            graph_area.draw_text(
+                &format!(
+                    "Network of {} out of {} agents",
+                    self.graph_layout.len(),
+                    time_step_results.n
+                ),
+                &text0,
+                (0, 0),
+            )?;
         }
         #[cfg(feature = "landscape")]
-        {
-            let mut chart = ChartBuilder::on(&left_panels[3])
+        if let Some(area) = panel_area(Panel::Landscape) {
+            // Comparison runs show their landscapes to the right of this one
+            let areas = area.split_evenly((1, 1 + self.comparisons.len()));
+            let draw_cells = |area: &DrawingArea<DB, plotters::coord::Shift>,
+                              coord: &WrappingCoords2d,
+                              cell_health: &[Health]| {
+                let cells = area.split_evenly((coord.height() as usize, coord.width() as usize));
+                cells
+                    .iter()
+                    .zip(cell_health.iter())
+                    .try_for_each(|(cell, health)| {
+                        cell.fill(match health {
+                            Health::S => color_s,
+                            Health::I => color_i,
+                        })
+                    })
+            };
+            let margin = scaled(10);
+            let landscape = areas[0].margin(margin, margin, margin, margin);
+            draw_cells(&landscape, coord, &time_step_results.cell_health)?;
+            if time_step_results.cell_health.is_empty() {
+                landscape.draw_text(
+                    &format!(
+                        "Only the latest {} landscapes are kept",
+                        MAX_LANDSCAPE_HISTORY
+                    ),
+                    &text1,
+                    (0, 0),
+                )?;
+            }
+            #[cfg(feature = "landscape-graphics")]
+            {
+                geometry.landscape = Some((landscape.get_base_pixel(), landscape.dim_in_pixel()));
+            }
+            if !self.comparisons.is_empty() {
+                landscape.draw_text(&scenario.label(), &text1, (0, 0))?;
+            }
+            for (run, area) in self.comparisons.iter().zip(areas.iter().skip(1)) {
+                let landscape = area.margin(margin, margin, margin, margin);
+                if let Some(run_results) = run.results_at(time_step_results.time_step) {
+                    draw_cells(&landscape, &run.coord, &run_results.cell_health)?;
+                }
+                landscape.draw_text(&run.scenario.label(), &text1, (0, 0))?;
+            }
+        }
+        // end-similar-code 7
+        if let (Some(area), Some(sweep)) = (panel_area(Panel::Sweep), &self.sweep) {
+            let (value_min, value_max) = sweep
+                .values
+                .iter()
+                .flatten()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &value| {
+                    (min.min(value), max.max(value))
+                });
+            let caption = if value_min <= value_max {
+                format!(
+                    "{}, mean of {} runs: {:.3} to {:.3}",
+                    sweep.outcome.label(),
+                    sweep.runs,
+                    value_min,
+                    value_max
+                )
+            } else if (sweep.next as usize) < sweep.values.len() {
+                format!("{}: the sweep is running", sweep.outcome.label())
+            } else {
+                format!(
+                    "{}: no grid point has valid parameters",
+                    sweep.outcome.label()
+                )
+            };
+            let mut chart = ChartBuilder::on(area)
                 .x_label_area_size(x_label_area_size)
                 .y_label_area_size(y_label_area_size)
                 .margin(figure_margin)
-                .caption("Infection of cells", text0.clone())
-                .build_cartesian_2d(0..(time_series_len as u32), 0..cell_time_series_height)
-                .unwrap();
+                .caption(&caption, text0.clone())
+                .build_cartesian_2d(sweep.x.range(), sweep.y.map_or(0.0..1.0, |y| y.range()))?;
             chart
                 .configure_mesh()
-                .light_line_style(&color01)
-                .bold_line_style(&color02)
-                .y_desc("Number of infected cells")
-                .x_desc("Time")
+                .disable_mesh()
+                .x_desc(sweep.x.parameter.label())
+                .y_desc(sweep.y.map_or("", |y| y.parameter.label()))
                 .axis_style(color0)
                 .axis_desc_style(text1.clone())
-                .label_style(text1)
-                .draw()
-                .unwrap();
-            chart
-                .draw_series(LineSeries::new(
-                    scenario
-                        .time_series
-                        .iter()
-                        .skip_while(|tsr| tsr.time_step < time_step_results.time_step)
-                        .map(|time_step_results| {
-                            (time_step_results.time_step, time_step_results.c_i)
-                        }),
-                    color_i,
-                ))
-                .unwrap();
-            chart
-                .draw_series(LineSeries::new(
-                    scenario
-                        .time_series
-                        .iter()
-                        .take_while(|tsr| tsr.time_step <= time_step_results.time_step)
-                        .map(|time_step_results| {
-                            (time_step_results.time_step, time_step_results.c_i)
-                        }),
-                    color_it,
+                .label_style(text1.clone())
+                .draw()?;
+            // Low values take the colour of susceptibles, high values the colour of infected agents
+            let half_width = sweep.x.range().end - sweep.x.max;
+            chart.draw_series(sweep.values.iter().enumerate().filter_map(|(idx, value)| {
+                let value = (*value)?;
+                let x = sweep.x.value(idx as u32 % sweep.x.steps);
+                let (y0, y1) = match sweep.y {
+                    Some(y) => {
+                        let half_height = y.range().end - y.max;
+                        let y_center = y.value(idx as u32 / sweep.x.steps);
+                        (y_center - half_height, y_center + half_height)
+                    }
+                    None => (0.0, 1.0),
+                };
+                let t = if value_max > value_min {
+                    (value - value_min) / (value_max - value_min)
+                } else {
+                    0.5
+                };
+                Some(Rectangle::new(
+                    [(x - half_width, y0), (x + half_width, y1)],
+                    blend(color_s, color_i, t).filled(),
                 ))
-                .unwrap();
+            }))?;
         }
-        #[cfg(feature = "landscape")]
+        #[cfg(feature = "infection-events")]
         {
-            let landscape = right_area.margin(10, 10, 10, 10);
-            let cells = landscape.split_evenly((coord.height() as usize, coord.width() as usize));
-            cells
+            // Infections up to the time step shown
+            let events = &scenario.infection_events;
+            let shown =
+                events.partition_point(|event| event.time_step < time_step_results.time_step);
+            if let Some(area) = panel_area(Panel::Tree) {
+                let events = &events[..shown.min(MAX_TREE_EVENTS)];
+                let tree = TransmissionTree::new(events);
+                let rows = tree.rows();
+                let time_max = events.last().map_or(10, |event| event.time_step.max(10));
+                let caption = if shown > MAX_TREE_EVENTS {
+                    format!(
+                        "Transmission tree of the first {} infections",
+                        MAX_TREE_EVENTS
+                    )
+                } else {
+                    "Transmission tree".to_string()
+                };
+                let mut chart = ChartBuilder::on(area)
+                    .x_label_area_size(x_label_area_size)
+                    .y_label_area_size(y_label_area_size)
+                    .margin(figure_margin)
+                    .caption(&caption, text0.clone())
+                    .build_cartesian_2d(0..time_max + 1, 0..events.len().max(1))?;
+                chart
+                    .configure_mesh()
+                    .disable_mesh()
+                    .y_labels(0)
+                    .x_desc("Time step of infection")
+                    .y_desc("Infections, by chain")
+                    .axis_style(color0)
+                    .axis_desc_style(text1.clone())
+                    .label_style(text1.clone())
+                    .draw()?;
+                // Each infector's row extends to the time of each of its secondary cases
+                chart.draw_series(tree.parents.iter().enumerate().filter_map(
+                    |(idx, parent)| {
+                        let parent = (*parent)?;
+                        Some(PathElement::new(
+                            vec![
+                                (events[parent].time_step, rows[parent]),
+                                (events[idx].time_step, rows[parent]),
+                                (events[idx].time_step, rows[idx]),
+                            ],
+                            color02,
+                        ))
+                    },
+                ))?;
+                let marker_size = scaled(3);
+                let route_color = |source: Source| match source {
+                    Source::Seed => color0,
+                    #[cfg(feature = "net")]
+                    Source::Neighbor(_) => color_i,
+                    #[cfg(feature = "landscape")]
+                    Source::Cell(_) => color1,
+                };
+                chart.draw_series(events.iter().zip(rows.iter()).map(|(event, &row)| {
+                    Circle::new(
+                        (event.time_step, row),
+                        marker_size,
+                        route_color(event.source).filled(),
+                    )
+                }))?;
+                // Colours of the routes
+                let routes = [
+                    ("seed", color0),
+                    #[cfg(feature = "net")]
+                    ("network", color_i),
+                    #[cfg(feature = "landscape")]
+                    ("landscape", color1),
+                ];
+                for (idx, (route, color)) in routes.iter().enumerate() {
+                    area.draw_text(route, &text1.color(*color), at(80 + 120 * idx as i32, 50))?;
+                }
+            }
+            if let Some(area) = panel_area(Panel::Secondary) {
+                let tree = TransmissionTree::new(&events[..shown]);
+                let mut histogram = vec![0u32; 1];
+                for &cases in &tree.secondary_cases {
+                    if histogram.len() <= cases as usize {
+                        histogram.resize(cases as usize + 1, 0);
+                    }
+                    histogram[cases as usize] += 1;
+                }
+                let mean = tree.secondary_cases.iter().sum::<u32>() as f64
+                    / f64::max(tree.secondary_cases.len() as f64, 1.0);
+                let mut chart = ChartBuilder::on(area)
+                    .x_label_area_size(x_label_area_size)
+                    .y_label_area_size(y_label_area_size)
+                    .margin(figure_margin)
+                    .caption(
+                        format!("Secondary cases in the network, mean {:.2}", mean),
+                        text0.clone(),
+                    )
+                    .build_cartesian_2d(
+                        -0.5..histogram.len() as f64 - 0.5,
+                        0..histogram.iter().max().copied().unwrap_or(0) + 1,
+                    )?;
+                chart
+                    .configure_mesh()
+                    .disable_mesh()
+                    .x_desc("Agents infected by an infected agent; recent infections can still add cases")
+                    .y_desc("Infections")
+                    .axis_style(color0)
+                    .axis_desc_style(text1.clone())
+                    .label_style(text1.clone())
+                    .draw()?;
+                chart.draw_series(histogram.iter().enumerate().map(|(cases, &count)| {
+                    Rectangle::new(
+                        [(cases as f64 - 0.4, 0), (cases as f64 + 0.4, count)],
+                        color_i.filled(),
+                    )
+                }))?;
+            }
+        }
+        #[cfg(feature = "net-graphics")]
+        if let Some(area) = panel_area(Panel::Metrics) {
+            let measured: std::vec::Vec<(u32, &NetMetrics)> = scenario
+                .time_series
                 .iter()
-                .zip(time_step_results.cell_health.iter())
-                .for_each(|(cell, health)| {
-                    cell.fill(match health {
-                        Health::S => color_s,
-                        Health::I => color_i,
-                    })
-                    .unwrap();
+                .filter(in_window)
+                .filter_map(|tsr| tsr.net.as_ref().map(|metrics| (tsr.time_step, metrics)))
+                .collect();
+            let charts = area.split_evenly((scenario.net_series.len().max(1), 1));
+            if scenario.net_series.is_empty() {
+                area.draw_text("Select network metrics to chart", &text1, at(50, 50))?;
+            }
+            for (&series, chart_area) in scenario.net_series.iter().zip(charts.iter()) {
+                let (y_min, y_max) =
+                    measured
+                        .iter()
+                        .fold((0.0f64, 0.0f64), |(min, max), (_t, metrics)| {
+                            let value = series.value(metrics);
+                            (min.min(value), max.max(value))
+                        });
+                // A little extra space in the chart
+                let y_margin = f64::max(0.05 * (y_max - y_min), 0.01);
+                let mut chart = ChartBuilder::on(chart_area)
+                    .x_label_area_size(x_label_area_size)
+                    .y_label_area_size(y_label_area_size)
+                    .margin(figure_margin)
+                    .caption(series.label(), text1.clone())
+                    .build_cartesian_2d(time_min..time_max, y_min - y_margin..y_max + y_margin)?;
+                chart
+                    .configure_mesh()
+                    .light_line_style(color01)
+                    .bold_line_style(color02)
+                    .y_labels(3)
+                    .axis_style(color0)
+                    .label_style(text1.clone())
+                    .draw()?;
+                let style = ShapeStyle::from(match series {
+                    NetSeries::MeanDegreeS => color_s,
+                    NetSeries::MeanDegreeI => color_i,
+                    _ => color1,
                 });
+                // Thin lines show the time steps after the current one
+                let future = measured
+                    .iter()
+                    .skip_while(|(t, _metrics)| *t < time_step_results.time_step)
+                    .map(|(t, metrics)| (*t, series.value(metrics)));
+                let past = measured
+                    .iter()
+                    .take_while(|(t, _metrics)| *t <= time_step_results.time_step)
+                    .map(|(t, metrics)| (*t, series.value(metrics)));
+                chart.draw_series(LineSeries::new(future, style))?;
+                chart.draw_series(LineSeries::new(past, style.stroke_width(thick_stroke)))?;
+            }
         }
-        // end-similar-code 7
+        Ok(geometry)
+    }
+
+    /// Describes the landscape cell under a canvas pixel; `toggle` infects or disinfects it.
+    /// Returns None outside the landscape.
+    #[cfg(feature = "landscape-graphics")]
+    fn inspect_cell(&mut self, x: i32, y: i32, toggle: bool) -> Option<String> {
+        let ((left, top), (width, height)) = self.geometry.landscape?;
+        if x < left || y < top || x >= left + width as i32 || y >= top + height as i32 {
+            return None;
+        }
+        let column = ((x - left) as i64 * self.coord.width() as i64 / width as i64) as i32;
+        let row = ((y - top) as i64 * self.coord.height() as i64 / height as i64) as i32;
+        let idx = self.coord.index(column, row);
+        if toggle && self.view.is_some() {
+            js_message("Branch the run from this time step to edit the landscape.");
+        } else if toggle {
+            // The simulation worker makes the same edit
+            let h = self.toggle_cell(idx)?;
+            js_toggle_cell(idx as u32);
+            js_message(&format!(
+                "Cell ({}, {}) is now {}.",
+                column,
+                row,
+                match h {
+                    Health::S => "susceptible",
+                    Health::I => "infected",
+                }
+            ));
+        }
+        let time_step_results = self.shown_results()?;
+        let health = match time_step_results.cell_health.get(idx)? {
+            Health::S => "susceptible",
+            Health::I => "infected",
+        };
+        // Visits are only kept for the latest time step
+        if self.view.is_some() {
+            return Some(format!(
+                "Cell ({}, {}): {} in time step {}.",
+                column, row, health, time_step_results.time_step
+            ));
+        }
+        let (visitors_s, visitors_i) = self.cell_visits.get(idx).copied().unwrap_or_default();
+        Some(format!(
+            "Cell ({}, {}): {}. Visitors in time step {}: {} susceptible, {} infected.",
+            column, row, health, time_step_results.time_step, visitors_s, visitors_i
+        ))
+    }
+}
+
+thread_local! {
+    /// Closures that src/app.js or src/worker.js call for the current scenario, and the model that they share.
+    /// Deploying another scenario drops them.
+    static SCENARIO_CLOSURES: RefCell<std::vec::Vec<Box<dyn std::any::Any>>> =
+        RefCell::new(std::vec::Vec::new());
+    /// Closure that runs the grid points of the latest parameter sweep; the next sweep drops it
+    #[cfg(feature = "graphics")]
+    static SWEEP_CLOSURE: RefCell<Option<Closure<dyn FnMut() -> bool>>> = RefCell::new(None);
+}
+
+/// Keeps the closures of a new scenario alive, and drops those of the previous one
+fn keep_scenario_closures(closures: std::vec::Vec<Box<dyn std::any::Any>>) {
+    SCENARIO_CLOSURES.with(|previous| previous.replace(closures));
+}
+
+#[wasm_bindgen]
+pub fn rs_deploy_scenario() {
+    // This provides better error messages in debug mode.
+    // It's disabled in release mode so it doesn't bloat up the file size.
+    #[cfg(debug_assertions)]
+    console_error_panic_hook::set_once();
+
+    // This model mirrors the one in the simulation worker, to draw its frames
+    let model = Rc::new(RefCell::new(Model::new()));
+    let frame_model = model.clone();
+    // Takes a frame without drawing it; returns false when a stop condition holds
+    let rs_frame_closure = Closure::wrap(Box::new(move |bytes: std::vec::Vec<u8>| {
+        let result = match &mut *frame_model.borrow_mut() {
+            Ok(model) => Frame::decode(&bytes).and_then(|frame| {
+                let running = frame.running;
+                model.apply_frame(frame)?;
+                js_timeline(
+                    model
+                        .scenario
+                        .time_series
+                        .last()
+                        .map_or(0, |tsr| tsr.time_step),
+                );
+                Ok(running)
+            }),
+            Err(e) => Err(e.clone()),
+        };
+        result.unwrap_or_else(|e| {
+            log_error(&e);
+            false
+        })
+    }) as Box<dyn FnMut(std::vec::Vec<u8>) -> bool>);
+    // Draws the latest frame, e.g. after a time step or after the canvas changes size
+    let render_model = model.clone();
+    let rs_render_closure = Closure::wrap(Box::new(move || {
+        if let Ok(model) = &mut *render_model.borrow_mut() {
+            if let Err(e) = model.read_figure_parameters().and_then(|_| model.render()) {
+                log_error(&e);
+            }
+            if let Some(time_step_results) = model.scenario.time_series.last() {
+                js_frame(time_step_results.time_step);
+            }
+        }
+    }) as Box<dyn FnMut()>);
+    // Describes the landscape cell under a canvas pixel and optionally toggles its infection
+    let cell_model = model.clone();
+    let rs_cell_closure = Closure::wrap(Box::new(move |x: i32, y: i32, toggle: bool| {
+        if let Ok(model) = &mut *cell_model.borrow_mut() {
+            if let Some(description) = model.inspect_cell(x, y, toggle) {
+                if toggle {
+                    if let Err(e) = model.render() {
+                        log_error(&e);
+                    }
+                }
+                return description;
+            }
+        }
+        String::new()
+    }) as Box<dyn FnMut(i32, i32, bool) -> String>);
+    // Shows the values under the pointer in the time series charts
+    let hover_model = model.clone();
+    let rs_hover_closure =
+        Closure::wrap(
+            Box::new(move |x: i32, y: i32| match &*hover_model.borrow() {
+                Ok(model) => model.hover(x, y).unwrap_or_else(|e| {
+                    log_error(&e);
+                    false
+                }),
+                Err(_) => false,
+            }) as Box<dyn FnMut(i32, i32) -> bool>,
+        );
+    // Zooms or pans the time axis of the time series charts
+    let zoom_model = model.clone();
+    let rs_zoom_closure = Closure::wrap(Box::new(move |x0: i32, x1: i32, y: i32, pan: bool| {
+        if let Ok(model) = &mut *zoom_model.borrow_mut() {
+            if model.zoom(x0, x1, y, pan) {
+                if let Err(e) = model.render() {
+                    log_error(&e);
+                }
+                return true;
+            }
+        }
+        false
+    }) as Box<dyn FnMut(i32, i32, i32, bool) -> bool>);
+    // Shows an earlier time step in the figures
+    let view_model = model.clone();
+    let rs_view_closure = Closure::wrap(Box::new(move |time_step: u32| {
+        if let Ok(model) = &mut *view_model.borrow_mut() {
+            model.set_view(time_step);
+            if let Err(e) = model.render() {
+                log_error(&e);
+            }
+        }
+    }) as Box<dyn FnMut(u32)>);
+    // Shows the latest parameter sweep in the sweep panel
+    let sweep_model = model.clone();
+    let rs_sweep_closure = Closure::wrap(Box::new(move |bytes: std::vec::Vec<u8>| {
+        if let Ok(model) = &mut *sweep_model.borrow_mut() {
+            if let Err(e) = model.show_sweep(&bytes).and_then(|_| model.render()) {
+                log_error(&e);
+            }
+        }
+    }) as Box<dyn FnMut(std::vec::Vec<u8>)>);
+    // Takes the bands that the replicate runs finished between frames
+    let bands_model = model.clone();
+    let rs_bands_closure = Closure::wrap(Box::new(move |bytes: std::vec::Vec<u8>| {
+        if let Ok(model) = &mut *bands_model.borrow_mut() {
+            match Band::decode(&bytes) {
+                Ok(bands) => model.bands.extend(bands),
+                Err(e) => log_error(&e),
+            }
+        }
+    }) as Box<dyn FnMut(std::vec::Vec<u8>)>);
+    let events_model = model.clone();
+    // Returns the infection events as CSV, or an empty string after an error
+    let rs_events_closure = Closure::wrap(Box::new(move || match &*events_model.borrow() {
+        Ok(model) => model.export_events(),
+        Err(e) => {
+            log_error(e);
+            String::new()
+        }
+    }) as Box<dyn FnMut() -> String>);
+    // Returns the current time step as SVG, or an empty string after an error
+    let rs_svg_closure = Closure::wrap(Box::new(move |width: u32, height: u32| {
+        match &*model.borrow() {
+            Ok(model) => model.export_svg(width, height),
+            Err(e) => Err(e.clone()),
+        }
+        .unwrap_or_else(|e| {
+            log_error(&e);
+            String::new()
+        })
+    }) as Box<dyn FnMut(u32, u32) -> String>);
+    js_scenario(
+        rs_frame_closure.as_ref().unchecked_ref(),
+        rs_render_closure.as_ref().unchecked_ref(),
+        rs_svg_closure.as_ref().unchecked_ref(),
+        rs_cell_closure.as_ref().unchecked_ref(),
+        rs_hover_closure.as_ref().unchecked_ref(),
+        rs_zoom_closure.as_ref().unchecked_ref(),
+        rs_view_closure.as_ref().unchecked_ref(),
+        rs_sweep_closure.as_ref().unchecked_ref(),
+        rs_events_closure.as_ref().unchecked_ref(),
+        rs_bands_closure.as_ref().unchecked_ref(),
+    );
+    // src/app.js no longer calls the closures of the previous scenario
+    keep_scenario_closures(vec![
+        Box::new(rs_frame_closure),
+        Box::new(rs_render_closure),
+        Box::new(rs_svg_closure),
+        Box::new(rs_cell_closure),
+        Box::new(rs_hover_closure),
+        Box::new(rs_zoom_closure),
+        Box::new(rs_view_closure),
+        Box::new(rs_sweep_closure),
+        Box::new(rs_events_closure),
+        Box::new(rs_bands_closure),
+    ]);
+}
+
+/// Deploys the model in the simulation worker, which runs the time steps off the main thread
+#[wasm_bindgen]
+pub fn rs_worker_deploy() {
+    #[cfg(debug_assertions)]
+    console_error_panic_hook::set_once();
+
+    // Replicate runs follow the parameters of this one
+    worker_scenario(Model::new().and_then(|mut model| {
+        model.add_replicates(js_replicates())?;
+        Ok(model)
+    }));
+}
+
+/// Runs a parameter sweep in the simulation worker, with the other parameters in the page.
+/// The page draws the heatmap as the grid points finish, one per worker task.
+#[wasm_bindgen]
+pub fn rs_worker_sweep() {
+    #[cfg(feature = "graphics")]
+    {
+        let result = Sweep::read().and_then(|sweep| {
+            let mut base = Model::new()?;
+            base.read_parameters()?;
+            if !base.scenario.dashboard.contains(Panel::Sweep) {
+                js_message("Add the sweep panel to the dashboard to see the heatmap.");
+            }
+            js_message(&format!(
+                "The sweep runs {} grid points, {} runs each.",
+                sweep.values.len(),
+                sweep.runs
+            ));
+            Ok((sweep, base))
+        });
+        match result {
+            Ok((mut sweep, base)) => {
+                // The worker runs one grid point per call, so that other messages run in between
+                let rs_sweep_point_closure = Closure::wrap(Box::new(move || {
+                    match sweep.run_point(&base) {
+                        Ok(true) => return true,
+                        Ok(false) => js_message("The sweep finished."),
+                        Err(e) => log_error(&e),
+                    }
+                    false
+                })
+                    as Box<dyn FnMut() -> bool>);
+                js_sweep_start(rs_sweep_point_closure.as_ref().unchecked_ref());
+                // src/worker.js stops calling the closure of the previous sweep
+                SWEEP_CLOSURE.with(|previous| previous.replace(Some(rs_sweep_point_closure)));
+            }
+            Err(e) => log_error(&e),
+        }
+    }
+    #[cfg(not(feature = "graphics"))]
+    js_error("Error: parameter sweeps need the figures of this build.");
+}
+
+/// Deploys a saved run in the simulation worker.
+/// Returns its model parameters for the page, or undefined when the run cannot be loaded.
+#[wasm_bindgen]
+pub fn rs_worker_load(bytes: std::vec::Vec<u8>) -> JsValue {
+    match Model::load(&bytes).and_then(|model| Ok((model.page_parameters()?, model))) {
+        Ok((parameters, model)) => {
+            js_message(&format!(
+                "Loaded a run saved at time step {}.",
+                model.time_step
+            ));
+            worker_scenario(Ok(model));
+            parameters.into()
+        }
+        Err(e) => {
+            js_error(&format!("Error: cannot load the run: {}.", e));
+            JsValue::UNDEFINED
+        }
+    }
+}
+
+/// Continues a saved run without the browser, for up to `steps` time steps or until a stop condition holds.
+/// The run keeps its saved parameters. Returns the saved state at the end.
+pub fn continue_saved_run(saved: &[u8], steps: u32) -> Result<std::vec::Vec<u8>, String> {
+    let mut model = Model::load(saved).map_err(|e| e.to_string())?;
+    let mut running = true;
+    for _ in 0..steps {
+        if !model.step().map_err(|e| e.to_string())? {
+            running = false;
+            break;
+        }
+    }
+    // The stop message already includes the summary
+    #[cfg(feature = "agent-metrics")]
+    if running {
+        if let Some(summary) = Summary::of(&model.scenario.time_series) {
+            js_message(&summary.lines().join(". "));
+        }
+    }
+    #[cfg(not(feature = "agent-metrics"))]
+    let _ = running;
+    model.save().map_err(|e| e.to_string())
+}
 
-        time_step += 1;
+/// Hands the closures of a worker model to src/worker.js
+fn worker_scenario(model: Result<Model, AbmError>) {
+    let model = Rc::new(RefCell::new(model));
+    let step_model = model.clone();
+    // Runs up to `steps` time steps; returns the encoded frame, or nothing after an error
+    let rs_step_closure = Closure::wrap(Box::new(move |steps: u32| {
+        match &mut *step_model.borrow_mut() {
+            Ok(model) => model
+                .step_n(steps)
+                .and_then(|running| model.frame(running).encode()),
+            Err(e) => Err(e.clone()),
+        }
+        .unwrap_or_else(|e| {
+            log_error(&e);
+            std::vec::Vec::new()
+        })
+    }) as Box<dyn FnMut(u32) -> std::vec::Vec<u8>>);
+    let toggle_model = model.clone();
+    let rs_toggle_cell_closure = Closure::wrap(Box::new(move |idx: u32| {
+        if let Ok(model) = &mut *toggle_model.borrow_mut() {
+            model.toggle_cell(idx as usize);
+        }
+    }) as Box<dyn FnMut(u32)>);
+    // Rewinds to the nearest snapshot, so that the next time steps use the current parameters
+    let branch_model = model.clone();
+    let rs_branch_closure = Closure::wrap(Box::new(move |time_step: u32| {
+        match &mut *branch_model.borrow_mut() {
+            Ok(model) => match model.restore(time_step) {
+                Some(snapshot_time_step) => {
+                    js_message(&format!(
+                        "A new run branches from time step {}, the nearest snapshot.",
+                        snapshot_time_step
+                    ));
+                    model.frame(true).encode()
+                }
+                None => Err(AbmError::Internal(format!(
+                    "no snapshot at or before time step {}",
+                    time_step
+                ))),
+            },
+            Err(e) => Err(e.clone()),
+        }
+        .unwrap_or_else(|e| {
+            log_error(&e);
+            std::vec::Vec::new()
+        })
+    }) as Box<dyn FnMut(u32) -> std::vec::Vec<u8>>);
+    // Returns the saved run, or nothing after an error
+    let save_model = model.clone();
+    let rs_save_closure = Closure::wrap(Box::new(move || {
+        match &*save_model.borrow() {
+            Ok(model) => model.save(),
+            Err(e) => Err(e.clone()),
+        }
+        .unwrap_or_else(|e| {
+            log_error(&e);
+            std::vec::Vec::new()
+        })
+    }) as Box<dyn FnMut() -> std::vec::Vec<u8>>);
+    // Advances one replicate run by one time step, in a worker task of its own.
+    // Returns the encoded new bands, empty when there are none, or nothing once the replicate runs caught up.
+    let replicates_model = model.clone();
+    let rs_replicates_closure = Closure::wrap(Box::new(move || {
+        let bands = match &mut *replicates_model.borrow_mut() {
+            Ok(model) => model.step_replicate(),
+            Err(_) => Ok(None),
+        };
+        bands
+            .and_then(|bands| match bands {
+                Some(bands) if bands.is_empty() => Ok(Some(std::vec::Vec::new())),
+                Some(bands) => Band::encode(&bands).map(Some),
+                None => Ok(None),
+            })
+            .unwrap_or_else(|e| {
+                log_error(&e);
+                None
+            })
+    })
+        as Box<dyn FnMut() -> Option<std::vec::Vec<u8>>>);
+    // Adds a comparison run with the parameters that src/worker.js puts in place
+    let rs_compare_closure = Closure::wrap(Box::new(move || {
+        if let Ok(model) = &mut *model.borrow_mut() {
+            match model.compare() {
+                Ok(true) => {}
+                Ok(false) => js_error(&format!(
+                    "Error: at most {} runs can be compared with the current parameters.",
+                    MAX_COMPARISONS
+                )),
+                Err(e) => log_error(&e),
+            }
+        }
     }) as Box<dyn FnMut()>);
-    js_scenario(rs_step_closure.as_ref().unchecked_ref());
-    rs_step_closure.forget();
+    js_worker_scenario(
+        rs_step_closure.as_ref().unchecked_ref(),
+        rs_toggle_cell_closure.as_ref().unchecked_ref(),
+        rs_branch_closure.as_ref().unchecked_ref(),
+        rs_save_closure.as_ref().unchecked_ref(),
+        rs_compare_closure.as_ref().unchecked_ref(),
+        rs_replicates_closure.as_ref().unchecked_ref(),
+    );
+    // src/worker.js no longer calls the closures of the previous scenario
+    keep_scenario_closures(vec![
+        Box::new(rs_step_closure),
+        Box::new(rs_toggle_cell_closure),
+        Box::new(rs_branch_closure),
+        Box::new(rs_save_closure),
+        Box::new(rs_compare_closure),
+        Box::new(rs_replicates_closure),
+    ]);
+}
+
+fn log_error(e: &AbmError) {
+    js_error(&format!(
+        "Error: {}. Please fix the parameters, then restart the simulation.",
+        e
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 500 agents that never get infected, so that only the demography changes `n`
+    fn healthy(demography: Demography, birth_rate: f64) -> Model {
+        let mut model = Model::with_size(500, 20).unwrap();
+        model.initial_infection_distro = Bernoulli::new(0.0).unwrap();
+        model.scenario.demography = demography;
+        model.scenario.birth_rate = birth_rate;
+        model
+    }
+
+    #[test]
+    fn immigration_is_constant() {
+        let mut model = healthy(Demography::Immigration, 0.5);
+        model.scenario.immigration = 7;
+        for _ in 0..10 {
+            model.step().unwrap();
+        }
+        assert_eq!(model.health.len(), 570);
+    }
+
+    #[test]
+    fn logistic_growth_approaches_the_carrying_capacity() {
+        let mut model = healthy(Demography::Logistic, 0.2);
+        model.scenario.carrying_capacity = 800;
+        for _ in 0..60 {
+            model.step().unwrap();
+        }
+        assert!(model.health.len() > 700);
+        assert!(model.health.len() <= 800);
+        // No births at or above the carrying capacity
+        for carrying_capacity in [500, 400] {
+            let mut model = healthy(Demography::Logistic, 0.2);
+            model.scenario.carrying_capacity = carrying_capacity;
+            for _ in 0..10 {
+                model.step().unwrap();
+            }
+            assert_eq!(model.health.len(), 500);
+        }
+    }
+
+    #[test]
+    fn all_births_include_infected_agents() {
+        let mut model = healthy(Demography::AllBirths, 0.05);
+        for _ in 0..10 {
+            model.step().unwrap();
+        }
+        assert!(model.health.len() > 650);
+        // Every agent gives birth, so the infected agents that survive add to the births of the recovered ones
+        let births = |demography| {
+            let mut model = healthy(demography, 1.0);
+            model.health.values_mut().for_each(|h| *h = Health::I);
+            model.step().unwrap();
+            model.health.len()
+        };
+        assert!(births(Demography::AllBirths) > births(Demography::SusceptibleBirths));
+    }
+
+    #[cfg(feature = "landscape-graphics")]
+    #[test]
+    fn landscape_history_is_capped() {
+        let mut time_series = std::vec::Vec::new();
+        for time_step in 0..MAX_LANDSCAPE_HISTORY + 5 {
+            time_series.push(TimeStepResults {
+                time_step: time_step as u32,
+                cell_health: vec![Health::S; 4],
+                ..Default::default()
+            });
+            forget_old_landscape(&mut time_series);
+        }
+        assert!(time_series[..5]
+            .iter()
+            .all(|tsr| tsr.cell_health.is_empty()));
+        assert!(time_series[5..]
+            .iter()
+            .all(|tsr| tsr.cell_health.len() == 4));
+    }
+
+    /// Number of infections during the first time steps of a run
+    #[cfg(all(
+        feature = "agent-metrics",
+        any(feature = "behavior", feature = "contact-tracing")
+    ))]
+    fn cumulative_incidence(mut model: Model, time_steps: u32) -> u32 {
+        for _ in 0..time_steps {
+            model.step().unwrap();
+        }
+        model
+            .scenario
+            .time_series
+            .iter()
+            .map(|tsr| tsr.incidence)
+            .sum()
+    }
+
+    #[cfg(all(feature = "behavior", feature = "agent-metrics"))]
+    #[test]
+    fn self_isolation_reduces_infections() {
+        let baseline = cumulative_incidence(epidemic(), 20);
+        let mut cautious = epidemic();
+        cautious.scenario.awareness = 10.0;
+        cautious.scenario.compliance = 0.9;
+        let isolated = cumulative_incidence(cautious, 20);
+        assert!(isolated < baseline);
+    }
+
+    #[cfg(all(feature = "contact-tracing", feature = "agent-metrics"))]
+    #[test]
+    fn contact_tracing_reduces_infections() {
+        let baseline = cumulative_incidence(epidemic(), 20);
+        let mut traced = epidemic();
+        traced.scenario.detection_probability = 0.5;
+        traced.scenario.tracing_capacity = 100;
+        // A duration of 0 still quarantines the agents for one time step
+        traced.scenario.quarantine_duration = 0;
+        traced.step().unwrap();
+        traced.step().unwrap();
+        assert!(traced.scenario.time_series[1].t_q > 0);
+        traced.scenario.quarantine_duration = 14;
+        let quarantined = cumulative_incidence(traced, 18);
+        assert!(quarantined < baseline);
+    }
+
+    /// Runs until a stop condition holds, checks that further steps change nothing, and returns the time step
+    fn run_until_stopped(model: &mut Model) -> u32 {
+        for _ in 0..100 {
+            if !model.step().unwrap() {
+                break;
+            }
+        }
+        assert!(model.stopped);
+        let results = model.scenario.time_series.len();
+        assert!(!model.step().unwrap());
+        assert_eq!(model.scenario.time_series.len(), results);
+        model.time_step
+    }
+
+    #[test]
+    fn stops_at_the_maximum_number_of_time_steps() {
+        let mut model = epidemic();
+        model.scenario.max_steps = 5;
+        assert_eq!(run_until_stopped(&mut model), 5);
+        assert_eq!(model.scenario.time_series.last().unwrap().time_step, 5);
+        // A higher limit resumes the run
+        model.scenario.max_steps = 8;
+        assert!(model.step().unwrap());
+        assert!(!model.stopped);
+        assert_eq!(run_until_stopped(&mut model), 8);
+    }
+
+    #[test]
+    fn stops_when_the_population_goes_extinct() {
+        let mut model = epidemic();
+        model.scenario.stop_on_population_extinction = true;
+        model.step().unwrap();
+        model.health.clear();
+        #[cfg(feature = "net")]
+        model.links.clear();
+        assert_eq!(run_until_stopped(&mut model), 1);
+    }
+
+    #[test]
+    fn stops_when_the_disease_goes_extinct() {
+        let mut model = healthy(Demography::SusceptibleBirths, 0.0);
+        model.scenario.stop_on_disease_extinction = true;
+        // The disease may emerge during the first time step
+        assert_eq!(run_until_stopped(&mut model), 1);
+    }
+
+    #[test]
+    fn stops_at_the_prevalence_threshold() {
+        let mut model = epidemic();
+        model.scenario.prevalence_threshold = 0.5;
+        model.health.values_mut().for_each(|h| *h = Health::I);
+        assert_eq!(run_until_stopped(&mut model), 0);
+    }
+
+    #[cfg(feature = "graphics")]
+    #[test]
+    fn dashboard_layouts() {
+        assert!(Dashboard::parse("  ").unwrap() == Dashboard::default());
+        let dashboard = Dashboard::parse(" header, agents:2 ,, | sweep").unwrap();
+        assert!(dashboard.charts == [(Panel::Header, 1), (Panel::Agents, 2)]);
+        assert!(dashboard.side == [(Panel::Sweep, 1)]);
+        for layout in [
+            "agents:0",
+            "agents:-1",
+            "agents:two",
+            "agents:",
+            "chart",
+            "header | agents | sweep",
+        ] {
+            assert!(Dashboard::parse(layout).is_err(), "{}", layout);
+        }
+    }
+
+    #[cfg(feature = "net-graphics")]
+    #[test]
+    fn degree_distribution() {
+        let histogram: BTreeMap<i32, u32> = [(0, 3), (1, 2), (2, 1), (4, 1)].into_iter().collect();
+        assert_eq!(degree_ccdf(&histogram), [(1, 1.0), (2, 0.5), (4, 0.25)]);
+        // One agent of degree 1: 1 + 1 / ln(1 / 0.5)
+        let single: BTreeMap<i32, u32> = [(0, 5), (1, 1)].into_iter().collect();
+        assert!((power_law_exponent(&single).unwrap() - (1.0 + 1.0 / 2f64.ln())).abs() < 1e-12);
+        let isolated: BTreeMap<i32, u32> = [(0, 5)].into_iter().collect();
+        assert!(power_law_exponent(&isolated).is_none());
+        assert!(degree_ccdf(&isolated).is_empty());
+    }
+
+    #[cfg(feature = "graphics")]
+    #[test]
+    fn colors() {
+        assert_eq!(parse_color("c", "#ff8000").unwrap(), (255, 128, 0));
+        assert_eq!(parse_color("c", "#0A0b0C").unwrap(), (10, 11, 12));
+        for value in [
+            "",
+            "#",
+            "ff8000",
+            "#ff800",
+            "#ff80000",
+            "#gg8000",
+            "#ff80\u{e9}",
+        ] {
+            assert!(parse_color("c", value).is_err(), "{}", value);
+        }
+    }
+
+    fn epidemic() -> Model {
+        let mut model = Model::with_size(500, 20).unwrap();
+        model.scenario.infection_probability = 0.1;
+        model.scenario.birth_rate = 0.02;
+        model
+    }
+
+    #[test]
+    fn slot_format() {
+        let mut map: SlotMap<AgentKey, Health> = SlotMap::with_key();
+        let keys: std::vec::Vec<AgentKey> = (0..4).map(|_| map.insert(Health::S)).collect();
+        map[keys[1]] = Health::I;
+        map.remove(keys[2]);
+        let bytes = bincode::serialize(&map).unwrap();
+        let slots: std::vec::Vec<Slot<Health>> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(bincode::serialize(&slots).unwrap(), bytes);
+        // Occupied slots have odd versions, like the keys; vacant slots have even versions
+        // Slot 0 is a sentinel; the index of a key is the position of its slot
+        assert_eq!(slots.len(), 5);
+        for (slot, &key) in slots[1..].iter().zip(&keys) {
+            match map.get(key) {
+                Some(&h) => {
+                    assert!(slot.value == Some(h));
+                    assert_eq!(slot.version as u64, slotmap::Key::data(&key).as_ffi() >> 32);
+                }
+                None => {
+                    assert!(slot.value.is_none());
+                    assert_eq!(
+                        slot.version as u64,
+                        (slotmap::Key::data(&key).as_ffi() >> 32) + 1
+                    );
+                }
+            }
+        }
+        // A deserialized map fills its vacant slot like the original one
+        let vacant = vacant_slots(&map, Health::S);
+        assert_eq!(vacant, [3]);
+        let deserialized: SlotMap<AgentKey, Health> = bincode::deserialize(&bytes).unwrap();
+        let mut refilled = refill_in_order(deserialized, &vacant, Health::S).unwrap();
+        assert!(refilled.insert(Health::S) == map.clone().insert(Health::S));
+    }
+
+    #[test]
+    fn saved_run_continues_like_an_uninterrupted_run() {
+        let mut uninterrupted = epidemic();
+        let mut saved = epidemic();
+        for _ in 0..10 {
+            uninterrupted.step().unwrap();
+            saved.step().unwrap();
+        }
+        let mut loaded = Model::load(&saved.save().unwrap()).unwrap();
+        for _ in 0..15 {
+            uninterrupted.step().unwrap();
+            loaded.step().unwrap();
+        }
+        assert_eq!(loaded.time_step, 25);
+        assert!(loaded.save().unwrap() == uninterrupted.save().unwrap());
+    }
+
+    #[cfg(feature = "agent-metrics")]
+    #[test]
+    fn median_and_band() {
+        // Nearest ranks 5, 50, and 95 of 0..100, whatever the order
+        assert_eq!(quantiles((1..=100).rev().map(f64::from)), [6.0, 51.0, 95.0]);
+        assert_eq!(quantiles([7.0].into_iter()), [7.0; 3]);
+        let results: std::vec::Vec<TimeStepResults> = [(10, 0), (20, 5), (40, 40)]
+            .iter()
+            .map(|&(n, i)| TimeStepResults {
+                n,
+                i,
+                ..Default::default()
+            })
+            .collect();
+        let band = Band::new(3, &results.iter().collect::<std::vec::Vec<_>>());
+        assert_eq!(band.replicates, 3);
+        assert_eq!(band.n, [10.0, 20.0, 40.0]);
+        assert_eq!(band.prevalence, [0.0, 0.25, 1.0]);
+    }
+
+    #[test]
+    fn replicates_catch_up_in_tasks_of_their_own() {
+        let mut model = epidemic();
+        model.add_replicates(3).unwrap();
+        for _ in 0..4 {
+            model.step().unwrap();
+        }
+        assert!(model.bands.is_empty());
+        let mut tasks = 0;
+        let mut bands = std::vec::Vec::new();
+        while let Some(new_bands) = model.step_replicate().unwrap() {
+            tasks += 1;
+            bands.extend(new_bands);
+        }
+        // One task per replicate and time step; a band once all replicates finish a time step
+        assert_eq!(tasks, 12);
+        assert!(bands.iter().map(|band| band.time_step).eq(0..4));
+        assert!(bands.iter().all(|band| band.replicates == 3));
+        assert!(model.replicates.iter().all(|run| run.time_step == 4));
+    }
+
+    #[cfg(feature = "graphics")]
+    #[test]
+    fn sweep_skips_invalid_grid_points() {
+        let mut base = Model::with_size(200, 10).unwrap();
+        base.scenario.birth_rate = 0.02;
+        let mut sweep = Sweep {
+            x: SweepAxis::new(SweepParameter::InfectionProbability, 0.0, 2.0, 3).unwrap(),
+            y: Some(SweepAxis::new(SweepParameter::BirthRate, 0.0, 0.02, 2).unwrap()),
+            outcome: Outcome::PeakInfected,
+            runs: 2,
+            time_steps: 10,
+            values: vec![None; 6],
+            next: 0,
+        };
+        let mut points = 1;
+        while sweep.run_point(&base).unwrap() {
+            points += 1;
+        }
+        assert_eq!(points, 6);
+        assert!(!sweep.run_point(&base).unwrap());
+        // Row by row: infection probabilities 0, 1, and 2, which is invalid
+        for row in sweep.values.chunks(3) {
+            let (Some(low), Some(high), None) = (row[0], row[1], row[2]) else {
+                panic!("unexpected grid points");
+            };
+            assert!(low < high);
+        }
+    }
+
+    #[cfg(feature = "agent-metrics")]
+    #[test]
+    fn summary_of_geometric_growth() {
+        // Incidence 46 * 2^t after 25 initial infections makes the infectiousness grow by the same factor,
+        // so every time step reproduces 2 (2 - q) / (1 - q) infections per unit of infectiousness
+        let time_series: std::vec::Vec<TimeStepResults> = (0..8)
+            .map(|t| TimeStepResults {
+                time_step: t,
+                n: 1_000_000,
+                i: if t == 0 { 25 } else { 46 << (t - 1) },
+                incidence: 46 << t,
+                ..Default::default()
+            })
+            .collect();
+        let summary = Summary::of(&time_series).unwrap();
+        let r = 2.0 * (2.0 - INFECTIOUS_STAY) / (1.0 - INFECTIOUS_STAY);
+        assert!((summary.r0.unwrap() - r).abs() < 1e-9);
+        assert!((summary.rt.unwrap() - r).abs() < 1e-9);
+        assert_eq!(summary.peak_infected, 46 << 6);
+        assert_eq!(summary.peak_time, 7);
+        assert_eq!(summary.attack_rate, (25.0 + 46.0 * 255.0) / 1_000_000.0);
+        assert_eq!(summary.duration, 7);
+        assert!(!summary.ended);
+        assert!(Summary::of(&[]).is_none());
+    }
+
+    #[cfg(all(feature = "infection-events", feature = "net"))]
+    #[test]
+    fn transmission_tree() {
+        let mut agents: SlotMap<AgentKey, ()> = SlotMap::with_key();
+        let [a, b, c, d, e] = [(); 5].map(|_| agents.insert(()));
+        let event = |infectee, source| InfectionEvent {
+            time_step: 0,
+            infectee,
+            source,
+        };
+        // A infects B and C, B infects D; A is infected again and then infects E
+        let tree = TransmissionTree::new(&[
+            event(a, Source::Seed),
+            event(b, Source::Neighbor(a)),
+            event(c, Source::Neighbor(a)),
+            event(d, Source::Neighbor(b)),
+            event(a, Source::Seed),
+            event(e, Source::Neighbor(a)),
+        ]);
+        assert_eq!(
+            tree.parents,
+            [None, Some(0), Some(0), Some(1), None, Some(4)]
+        );
+        assert_eq!(tree.secondary_cases, [2, 1, 0, 0, 1, 0]);
+        // The chain A, B, D takes consecutive rows
+        #[cfg(feature = "graphics")]
+        assert_eq!(tree.rows(), [0, 1, 3, 2, 4, 5]);
+    }
+
+    /// Agents with the given health, and links between them by position
+    #[cfg(feature = "net-metrics")]
+    fn network(
+        health: &[Health],
+        links: &[(usize, usize)],
+    ) -> (
+        SlotMap<AgentKey, Health>,
+        SlotMap<LinkKey, (AgentKey, AgentKey)>,
+    ) {
+        let mut agents = SlotMap::with_key();
+        let keys: std::vec::Vec<AgentKey> = health.iter().map(|&h| agents.insert(h)).collect();
+        let mut link_map = SlotMap::with_key();
+        for &(idx0, idx1) in links {
+            link_map.insert((keys[idx0], keys[idx1]));
+        }
+        (agents, link_map)
+    }
+
+    #[cfg(feature = "net-metrics")]
+    #[test]
+    fn triangle_and_isolated_agent() {
+        let (health, links) = network(
+            &[Health::I, Health::S, Health::S, Health::S],
+            &[(0, 1), (1, 2), (2, 0)],
+        );
+        let metrics = NetMetrics::measure(&health, &links);
+        assert_eq!(metrics.mean_degree, 1.5);
+        assert_eq!(metrics.mean_degree_i, 2.0);
+        // Degrees 2, 2, and 0 among the susceptibles
+        assert!((metrics.mean_degree_s - 4.0 / 3.0).abs() < 1e-12);
+        // The isolated agent counts as 0
+        assert_eq!(metrics.clustering, 0.75);
+        // Every link joins two agents of degree 2
+        assert_eq!(metrics.assortativity, 0.0);
+        assert_eq!(metrics.components, 2);
+        assert_eq!(metrics.largest_component, 3);
+    }
+
+    #[cfg(feature = "net-metrics")]
+    #[test]
+    fn star_is_disassortative() {
+        let (health, links) = network(&[Health::S; 5], &[(0, 1), (0, 2), (0, 3), (4, 0), (1, 0)]);
+        let metrics = NetMetrics::measure(&health, &links);
+        // The repeated link counts once
+        assert_eq!(metrics.mean_degree, 1.6);
+        assert_eq!(metrics.clustering, 0.0);
+        assert!((metrics.assortativity + 1.0).abs() < 1e-12);
+        assert_eq!(metrics.components, 1);
+        assert_eq!(metrics.largest_component, 5);
+    }
+
+    #[cfg(all(feature = "net-metrics", feature = "agent-metrics"))]
+    #[test]
+    fn network_measured_at_the_interval() {
+        let mut model = epidemic();
+        model.scenario.net_metrics_interval = 3;
+        for _ in 0..10 {
+            model.step().unwrap();
+        }
+        let series = &model.scenario.time_series;
+        let measured = series.iter().filter(|tsr| tsr.net.is_some());
+        assert!(measured.map(|tsr| tsr.time_step).eq([0, 3, 6, 9]));
+        for tsr in series {
+            if let Some(metrics) = &tsr.net {
+                assert!(metrics.components >= 1);
+                assert!(metrics.largest_component <= tsr.n);
+            }
+        }
+    }
 }
@@ -0,0 +1,107 @@
+/* This file is part of wasm-agent-based-models:
+   Reliable and efficient agent-based models in Rust and WebAssembly
+
+    Copyright 2020 Fabio A. Correa Duran facorread@gmail.com
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at
+
+       http://www.apache.org/licenses/LICENSE-2.0
+
+   Unless required by applicable law or agreed to in writing, software
+   distributed under the License is distributed on an "AS IS" BASIS,
+   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+   See the License for the specific language governing permissions and
+   limitations under the License.
+*/
+
+"use strict";
+
+// Runs the simulation off the main thread. The page posts the parameters with each message,
+// and the worker answers each time step with a frame that the page draws.
+self.abm = {
+    params: {},
+    message: function (msg, error) {
+        self.postMessage({ type: "message", msg: msg, error: error });
+    },
+    sweep: function (bytes) {
+        self.postMessage({ type: "sweep", bytes: bytes }, [bytes.buffer]);
+    },
+    // Runs one grid point per task, so that the page can step the live run meanwhile; a new sweep replaces this one
+    sweep_start: function (rs_sweep_point) {
+        self.abm.rs_sweep_point = rs_sweep_point;
+        function next_point() {
+            if (self.abm.rs_sweep_point === rs_sweep_point && rs_sweep_point()) {
+                setTimeout(next_point, 0);
+            }
+        }
+        setTimeout(next_point, 0);
+    },
+    // Runs the replicates one time step of one run per task until they catch up with the live run;
+    // the bands go to the page of scenario `id`
+    replicates_start: function (id) {
+        if (self.abm.replicates_id === id) {
+            return;
+        }
+        self.abm.replicates_id = id;
+        function next_replicate_step() {
+            if (self.abm.replicates_id !== id) {
+                return;
+            }
+            let bytes = self.abm.rs_replicates();
+            if (bytes === undefined) {
+                self.abm.replicates_id = undefined;
+                return;
+            }
+            if (bytes.length !== 0) {
+                self.postMessage({ type: "bands", id: id, bytes: bytes }, [bytes.buffer]);
+            }
+            setTimeout(next_replicate_step, 0);
+        }
+        setTimeout(next_replicate_step, 0);
+    },
+};
+
+// Webpack requires WebAssembly to be a dynamic import for now.
+const abm_module = import("/pkg/index.js");
+
+// Messages are handled in order, after the module loads
+self.addEventListener("message", function (event) {
+    abm_module.then(function (module) {
+        let data = event.data;
+        self.abm.params = data.params;
+        if (data.type === "deploy") {
+            // The replicates of the previous scenario stop
+            self.abm.replicates_id = undefined;
+            module.rs_worker_deploy();
+            // Each comparison run reads its pinned parameters once
+            for (let params of data.comparisons) {
+                self.abm.params = params;
+                self.abm.rs_compare();
+            }
+            self.abm.params = data.params;
+        } else if (data.type === "step") {
+            let frame = self.abm.rs_step(data.steps);
+            self.postMessage({ type: "frame", id: data.id, frame: frame }, [frame.buffer]);
+            self.abm.replicates_start(data.id);
+        } else if (data.type === "branch") {
+            let frame = self.abm.rs_branch(data.time_step);
+            self.postMessage({ type: "frame", id: data.id, frame: frame }, [frame.buffer]);
+            self.abm.replicates_start(data.id);
+        } else if (data.type === "toggle-cell") {
+            self.abm.rs_toggle_cell(data.idx);
+        } else if (data.type === "sweep") {
+            module.rs_worker_sweep();
+        } else if (data.type === "save") {
+            let bytes = self.abm.rs_save();
+            self.postMessage({ type: "saved", bytes: bytes }, [bytes.buffer]);
+        } else if (data.type === "load") {
+            self.abm.replicates_id = undefined;
+            let params = module.rs_worker_load(data.bytes);
+            if (params !== undefined) {
+                self.postMessage({ type: "loaded", params: params });
+            }
+        }
+    }, console.error);
+});